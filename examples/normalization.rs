@@ -0,0 +1,39 @@
+use cdc_algorithms::chunking::hash::random_data;
+use cdc_algorithms::{size_stats, ChunkInfo, Chunker, ChunkerError, CutReason, FastCdcConfig};
+
+// chunk-size distribution of FastCdcChunker for every normalization level on pseudo-random data
+fn main() -> Result<(), ChunkerError> {
+    let data = random_data(64 << 20, 1);
+
+    println!(
+        "| Expected | Level | Mean | Std dev | In [expected / 2, expected * 2] | Max size cuts |"
+    );
+    println!(
+        "|----------|-------|------|---------|---------------------------------|---------------|"
+    );
+    for expected_size in [4096, 8192, 65536] {
+        for level in 0..=3 {
+            let chunker = FastCdcConfig::new(expected_size)
+                .normalization(level)
+                .build()?;
+            let chunks: Vec<ChunkInfo> = chunker.boundaries(&data).collect();
+            let (mean, deviation) = size_stats(&chunks);
+            let share = |predicate: &dyn Fn(&ChunkInfo) -> bool| {
+                100. * chunks.iter().filter(|info| predicate(info)).count() as f64
+                    / chunks.len() as f64
+            };
+
+            println!(
+                "| {} | {} | {:.0} | {:.0} | {:.1}% | {:.2}% |",
+                expected_size,
+                level,
+                mean,
+                deviation,
+                share(&|info| (expected_size / 2..=expected_size * 2).contains(&info.length)),
+                share(&|info| info.reason == CutReason::MaxSize),
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -1,152 +1,193 @@
-use rayon::prelude::{IntoParallelIterator, ParallelIterator};
-
 use super::chunker::*;
+use super::parallel;
 use std::cmp::max;
-use std::collections::HashSet;
-use std::collections::hash_map::DefaultHasher;
 use std::f64::consts;
-use std::fs::File;
-use std::hash::{Hash, Hasher};
-use std::io::{Read, Write, Error};
-use std::ptr;
-use std::time::{Instant, Duration};
+use std::time::Duration;
 
-// "AE: An Asymmetric Extremum Content Defined Chunking Algorithm for Fast and Bandwidth-Efficient Data Deduplication"
-pub struct AeChunker {
-    buffer: [u8; 4096], // for buffered reading from input(4096 is max size of chunk)
-    buffered: usize,    // count of first in buffer already taken bytes
-    window_size: usize, // size for extremum window
+// extremum chunk ends after
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AeExtremum {
+    Max, // AE-Max from paper
+    Min, // AE-Min from paper
 }
 
-impl AeChunker {
-    pub fn new(expected_size: usize) -> AeChunker {
-        AeChunker {
-            buffer: [0; 4096],
-            buffered: 0,
-            window_size: expected_size - 256,
-            // window_size: ((expected_size as f64) / (consts::E - 1.)).round() as usize, // from paper
+// values compared at every position, wide values are the bytes ending at position read as big-endian
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AeValue {
+    U8,
+    U32,
+    U64,
+}
+
+// parameters of AeChunker
+#[derive(Clone, Debug)]
+pub struct AeConfig {
+    min_size: usize,
+    expected_size: usize,
+    max_size: usize,
+    window_size: Option<usize>, // size for extremum window, default depends on values
+    extremum: AeExtremum,       // max or min is searched
+    value: AeValue,             // width of compared values
+    buffer_size: usize,         // initial size of buffer for reading from input
+}
+
+impl AeConfig {
+    pub fn new(expected_size: usize) -> AeConfig {
+        AeConfig {
+            // extremum is searched from the beginning of chunk by default, as in paper
+            min_size: 0,
+            expected_size,
+            max_size: expected_size.saturating_mul(4),
+            window_size: None,
+            extremum: AeExtremum::Max,
+            value: AeValue::U8,
+            buffer_size: DEFAULT_BUFFER_SIZE,
         }
     }
 
-    fn get_bounds(&self, vec: &Vec<u8>, left: usize, right: usize) -> Vec<usize> {
-        let mut result = Vec::new();
-        let start = max(0i64, (left as i64) - (self.window_size as i64)) as usize;
-        let mut max_val = vec[start];
-        let mut max_pos = start;
-        for i in start..right {
-            if vec[i] > max_val {
-                max_val = vec[i];
-                max_pos = i;
-            } else if i == max_pos + self.window_size {
-                result.push(i);
-                if i + 1 != right {
-                    max_val = vec[i + 1];
-                    max_pos = i + 1;
-                }
-            }
-        }
-    
-        if right == vec.len() {
-            result.push(vec.len() - 1);
+    pub fn min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    pub fn window_size(mut self, window_size: usize) -> Self {
+        self.window_size = Some(window_size);
+        self
+    }
+
+    pub fn extremum(mut self, extremum: AeExtremum) -> Self {
+        self.extremum = extremum;
+        self
+    }
+
+    pub fn value(mut self, value: AeValue) -> Self {
+        self.value = value;
+        self
+    }
+
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    fn default_window_size(&self) -> usize {
+        let paper_window = ((self.expected_size as f64) / (consts::E - 1.)).round() as usize;
+        match self.value {
+            // on random data max byte is found in ~256 bytes, so mean chunk is ~window + 256,
+            // for small sizes window from paper is used (it gives smaller chunks than expected)
+            AeValue::U8 => max(self.expected_size.saturating_sub(256), paper_window),
+            // wide values almost never repeat, so they behave as continuous ones from paper
+            AeValue::U32 | AeValue::U64 => paper_window,
         }
-        result
     }
-    
-    pub fn parallel_chunking(&self, path: &str, threads_cnt: usize) -> (Duration, f64, f64) {
-        let vec = Chunker::read_file(self, path).unwrap();
-    
-        let now = Instant::now();
-        let tmp: Vec<Vec<usize>> = (1..threads_cnt + 1)
-                .into_par_iter()
-                .map(|i| {
-                    let left = (i - 1) * vec.len() / threads_cnt;
-                    let right = i * vec.len() / threads_cnt;
-                    self.get_bounds(&vec,  left, right)
-                })
-                .collect();
-    
-        let mut last: i64 = -1;
-        let mut set = HashSet::new();
-        let mut local_sm = 0;
-        let mut sm = 0;
-        for j in 0..threads_cnt {
-            for &elem in &tmp[j] {
-                if (elem as i64) - last >= (self.window_size as i64) {
-                    let next = &vec[((last + 1) as usize)..elem + 1];
-                    let mut hasher = DefaultHasher::new();
-                    next.hash(&mut hasher);
-                    let hsh = hasher.finish();
-    
-                    if !set.contains(&hsh) {
-                        set.insert(hsh);
-                        local_sm += (elem as i64) - last;
-                    }
-                    sm += (elem as i64) - last;
-                    last = elem as i64;
-                }
-            }
+
+    pub fn build(self) -> Result<AeChunker, ChunkerError> {
+        if self.expected_size == 0 {
+            return Err(ChunkerError::New("expected size must be positive"));
         }
-        
-        let elapsed = now.elapsed();
-        (elapsed, (local_sm as f64) / (vec.len() as f64), (sm as f64) / (set.len() as f64))
+        if self.min_size > self.expected_size || self.expected_size > self.max_size {
+            return Err(ChunkerError::New("sizes must satisfy min <= expected <= max"));
+        }
+        let window_size = self.window_size.unwrap_or_else(|| self.default_window_size());
+        if window_size == 0 {
+            return Err(ChunkerError::New("window size must be positive"));
+        }
+        if self.buffer_size == 0 {
+            return Err(ChunkerError::New("buffer size must be positive"));
+        }
+
+        Ok(AeChunker {
+            stream: ChunkStream::new(self.buffer_size),
+            window_size,
+            min_size: self.min_size,
+            max_size: self.max_size,
+            extremum: self.extremum,
+            value: self.value,
+            config: self,
+        })
     }
 }
 
-impl Chunker for AeChunker {
-    fn next_chunk(
-        &mut self,
-        input: &mut dyn Read,
-        output: &mut dyn Write,
-    ) -> Result<ChunkerStatus, ChunkerError> {
-        let mut local_pos = 0;
-        let mut max_pos = 0;
-        let mut max_val = 0;
-        loop {
-            // [0, self.buffered - 1] is already taken bytes
-            // max is end of data if it less than buffer size
-            let max = input
-                .read(&mut self.buffer[self.buffered..])
-                .map_err(ChunkerError::Read)?
-                + self.buffered;
-
-            // if max == 0 then end of input reached
-            if max == 0 {
-                return Ok(ChunkerStatus::Finished);
-            }
+// value of size bytes ending at pos
+fn value_at<const SIZE: usize>(data: &[u8], pos: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes[8 - SIZE..].copy_from_slice(&data[pos + 1 - SIZE..=pos]);
+    u64::from_be_bytes(bytes)
+}
 
-            for i in 0..max {
-                let cur_val = self.buffer[i];
+// "AE: An Asymmetric Extremum Content Defined Chunking Algorithm for Fast and Bandwidth-Efficient Data Deduplication"
+pub struct AeChunker {
+    stream: ChunkStream,  // for buffered reading from input
+    window_size: usize,   // size for extremum window
+    min_size: usize,      // no values are compared in first min_size bytes of chunk
+    max_size: usize,      // max size of chunk
+    extremum: AeExtremum, // max or min is searched
+    value: AeValue,       // width of compared values
+    config: AeConfig,     // parameters chunker was built with
+}
+
+impl AeChunker {
+    pub fn new(expected_size: usize) -> Result<AeChunker, ChunkerError> {
+        AeConfig::new(expected_size).build()
+    }
+
+    pub fn config(&self) -> &AeConfig {
+        &self.config
+    }
+
+    pub fn parallel_chunking(
+        &self,
+        path: &str,
+        threads_cnt: usize,
+    ) -> Result<(Duration, f64, f64), ChunkerError> {
+        parallel::parallel_chunking(self, path, threads_cnt)
+    }
+
+    fn find_cut_with<const SIZE: usize>(&self, data: &[u8]) -> (usize, CutReason) {
+        let start = self.min_size.max(SIZE - 1);
+        let end = data.len().min(self.max_size);
+        if start < end {
+            // inverted values turn min into max
+            let flip = match self.extremum {
+                AeExtremum::Max => 0,
+                AeExtremum::Min => u64::MAX,
+            };
+            let mut max_pos = start;
+            let mut max_val = value_at::<SIZE>(data, max_pos) ^ flip;
+            for i in start + 1..end {
+                let cur_val = value_at::<SIZE>(data, i) ^ flip;
                 if cur_val > max_val {
                     max_val = cur_val;
-                    max_pos = local_pos;
-                } else if local_pos == max_pos + self.window_size {
-                    // finded new chunk, write it
-                    output
-                        .write_all(&self.buffer[..i + 1])
-                        .map_err(ChunkerError::Write)?;
-
-                    // move unprocessed data to the beginning of buffer
-                    unsafe {
-                        ptr::copy(
-                            self.buffer[i + 1..].as_ptr(),
-                            self.buffer.as_mut_ptr(),
-                            max - i - 1,
-                        )
-                    };
-                    self.buffered = max - i - 1;
-
-                    return Ok(ChunkerStatus::Working);
+                    max_pos = i;
+                } else if i == max_pos + self.window_size {
+                    return (i + 1, CutReason::Hash);
                 }
-                local_pos += 1;
             }
+        }
 
-            // end of buffer or end of input reached
-            output
-                .write_all(&self.buffer[..max])
-                .map_err(ChunkerError::Write)?;
-            self.buffered = 0;
+        if data.len() >= self.max_size {
+            (self.max_size, CutReason::MaxSize)
+        } else {
+            (data.len(), CutReason::Eof)
+        }
+    }
+}
+
+impl Chunker for AeChunker {
+    fn find_cut(&self, data: &[u8]) -> (usize, CutReason) {
+        match self.value {
+            AeValue::U8 => self.find_cut_with::<1>(data),
+            AeValue::U32 => self.find_cut_with::<4>(data),
+            AeValue::U64 => self.find_cut_with::<8>(data),
         }
     }
 
-    
+    fn stream(&mut self) -> &mut ChunkStream {
+        &mut self.stream
+    }
 }
@@ -0,0 +1,221 @@
+use super::chunker::*;
+use super::hash::generate_seq;
+
+// "Recursive n-gram hashing is pairwise independent, at best" (cyclic polynomial hash)
+// chunking rule is modelled on borg: window starts at candidate cut point after min_size bytes
+// of chunk and must end before the end of input (or max_size), chunk is cut at the start
+// of the first window which hash has zero bits under mask;
+// borg's base table (table_base of its _chunker.c) is not bundled, so BuzHashConfig::borg takes it
+
+// cyclic polynomial hash of the last window_size bytes
+pub(crate) struct BuzHash {
+    table: [u32; 256], // substitution value for every byte
+    window_size: usize,
+}
+
+impl BuzHash {
+    pub fn new(table: [u32; 256], window_size: usize) -> BuzHash {
+        BuzHash { table, window_size }
+    }
+
+    // value of byte at position pos from the end of window is rotated by pos
+    pub fn hash(&self, window: &[u8]) -> u32 {
+        window.iter().fold(0u32, |hash, &cur_val| {
+            hash.rotate_left(1) ^ self.table[cur_val as usize]
+        })
+    }
+
+    // move window by one byte: front leaves it, back enters it
+    pub fn roll(&self, hash: u32, front: u8, back: u8) -> u32 {
+        hash.rotate_left(1)
+            ^ self.table[front as usize].rotate_left(self.window_size as u32)
+            ^ self.table[back as usize]
+    }
+}
+
+// parameters of BuzHashChunker
+#[derive(Clone, Debug)]
+pub struct BuzHashConfig {
+    min_size: usize,
+    expected_size: usize,
+    max_size: usize,
+    window_size: usize,        // size of hashed window
+    seed: u64, // seed for generated substitution table, low 32 bits are xor-ed with own one
+    table: Option<[u32; 256]>, // own base substitution table instead of generated from seed
+    mask_bits: Option<u32>, // bits count of mask instead of derived from expected size
+    buffer_size: usize, // initial size of buffer for reading from input
+}
+
+impl BuzHashConfig {
+    pub fn new(expected_size: usize) -> BuzHashConfig {
+        BuzHashConfig {
+            min_size: expected_size / 4,
+            expected_size,
+            max_size: expected_size.saturating_mul(4),
+            // 4095 is the window of borg
+            window_size: (expected_size / 4).clamp(1, 4095),
+            seed: 0,
+            table: None,
+            mask_bits: None,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+        }
+    }
+
+    // borg's base table with its chunker params (e.g. 19, 23, 21, 4095): min and max sizes are
+    // powers of two, chunk is cut when hash_mask_bits low bits of hash are zero;
+    // chunker seed of borg repository is given by seed
+    pub fn borg(
+        table_base: [u32; 256],
+        chunk_min_exp: u32,
+        chunk_max_exp: u32,
+        hash_mask_bits: u32,
+        hash_window_size: usize,
+    ) -> BuzHashConfig {
+        let pow = |exp: u32| 1usize.checked_shl(exp).unwrap_or(0);
+        let (min_size, max_size) = (pow(chunk_min_exp), pow(chunk_max_exp));
+        BuzHashConfig::new(pow(hash_mask_bits).clamp(min_size, max_size.max(min_size)))
+            .min_size(min_size)
+            .max_size(max_size)
+            .mask_bits(hash_mask_bits)
+            .window_size(hash_window_size)
+            .table(table_base)
+    }
+
+    pub fn min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    pub fn window_size(mut self, window_size: usize) -> Self {
+        self.window_size = window_size;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    // values are xor-ed with low 32 bits of seed, as borg does with its base table
+    pub fn table(mut self, table: [u32; 256]) -> Self {
+        self.table = Some(table);
+        self
+    }
+
+    pub fn mask_bits(mut self, mask_bits: u32) -> Self {
+        self.mask_bits = Some(mask_bits);
+        self
+    }
+
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    pub fn build(self) -> Result<BuzHashChunker, ChunkerError> {
+        if self.expected_size == 0 {
+            return Err(ChunkerError::New("expected size must be positive"));
+        }
+        if self.expected_size > 1 << 32 {
+            return Err(ChunkerError::New("expected size is too big"));
+        }
+        if self.min_size > self.expected_size || self.expected_size > self.max_size {
+            return Err(ChunkerError::New(
+                "sizes must satisfy min <= expected <= max",
+            ));
+        }
+        if self.window_size == 0 {
+            return Err(ChunkerError::New("window size must be positive"));
+        }
+        if self
+            .min_size
+            .checked_add(self.window_size)
+            .is_none_or(|size| size >= self.max_size)
+        {
+            return Err(ChunkerError::New(
+                "min size with window must be less than max size",
+            ));
+        }
+        if self.mask_bits.is_some_and(|bits| bits > 32) {
+            return Err(ChunkerError::New("mask must have at most 32 bits"));
+        }
+        if self.buffer_size == 0 {
+            return Err(ChunkerError::New("buffer size must be positive"));
+        }
+
+        let table = match self.table {
+            Some(table) => table.map(|value| value ^ self.seed as u32),
+            // high bits of generated values are the most random ones
+            None => generate_seq(self.seed).map(|value| (value >> 32) as u32),
+        };
+        let mask = match self.mask_bits {
+            Some(bits) => u32::MAX.checked_shr(32 - bits).unwrap_or(0),
+            None => (self.expected_size.next_power_of_two() - 1) as u32,
+        };
+        Ok(BuzHashChunker {
+            stream: ChunkStream::new(self.buffer_size),
+            hash: BuzHash::new(table, self.window_size),
+            min_size: self.min_size,
+            max_size: self.max_size,
+            mask,
+            config: self,
+        })
+    }
+}
+
+pub struct BuzHashChunker {
+    stream: ChunkStream,   // for buffered reading from input
+    hash: BuzHash,         // rolling hash of window
+    min_size: usize,       // min size of chunk
+    max_size: usize,       // max size of chunk
+    mask: u32,             // chunk is cut when hash & mask == 0
+    config: BuzHashConfig, // parameters chunker was built with
+}
+
+impl BuzHashChunker {
+    pub fn new(expected_size: usize, seed: u64) -> Result<BuzHashChunker, ChunkerError> {
+        BuzHashConfig::new(expected_size).seed(seed).build()
+    }
+
+    pub fn config(&self) -> &BuzHashConfig {
+        &self.config
+    }
+}
+
+impl Chunker for BuzHashChunker {
+    fn find_cut(&self, data: &[u8]) -> (usize, CutReason) {
+        let window_size = self.hash.window_size;
+        // window starts at cut and ends before the end of data or max_size, empty chunk is never cut
+        let first = self.min_size.max(1);
+        let limit = data.len().min(self.max_size);
+
+        if first + window_size < limit {
+            let mut hash = self.hash.hash(&data[first..first + window_size]);
+            for cut in first..limit - window_size {
+                if cut > first {
+                    hash = self
+                        .hash
+                        .roll(hash, data[cut - 1], data[cut - 1 + window_size]);
+                }
+                if hash & self.mask == 0 {
+                    return (cut, CutReason::Hash);
+                }
+            }
+        }
+
+        if data.len() >= self.max_size {
+            (self.max_size, CutReason::MaxSize)
+        } else {
+            (data.len(), CutReason::Eof)
+        }
+    }
+
+    fn stream(&mut self) -> &mut ChunkStream {
+        &mut self.stream
+    }
+}
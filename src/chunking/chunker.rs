@@ -1,6 +1,7 @@
 use quick_error::quick_error;
-use std::{io::{self, Read, Write, Error}, fs::File};
-#[derive(PartialEq)]
+use sha2::{Digest, Sha256};
+use std::{io::{self, Read, Write, Error}, fs::File, ops::Range};
+#[derive(PartialEq, Debug)]
 pub enum ChunkerStatus {
     Working,
     Finished,
@@ -21,12 +22,85 @@ quick_error! {
     }
 }
 
+// why chunk was cut
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum CutReason {
+    Hash,    // content-defined cut point (hash match, extremum, ...)
+    MaxSize, // size limit of chunk reached
+    Member,  // boundary of member of archive (tar-aware chunking)
+    Eof,     // end of input reached
+}
+
+// metadata of emitted chunk
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct ChunkInfo {
+    pub offset: u64,              // absolute offset of chunk in input
+    pub length: usize,            // length of chunk
+    pub reason: CutReason,        // why chunk was cut
+    pub hash: Option<[u8; 32]>,   // SHA-256 of chunk data if it was requested
+}
+
+impl ChunkInfo {
+    pub fn range(&self) -> Range<usize> {
+        self.offset as usize..self.offset as usize + self.length
+    }
+
+    // last chunk of input is the one cut by end of input
+    pub fn status(&self) -> ChunkerStatus {
+        if self.reason == CutReason::Eof {
+            ChunkerStatus::Finished
+        } else {
+            ChunkerStatus::Working
+        }
+    }
+}
+
+pub fn strong_hash(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
+
 pub trait Chunker {
+    // length of the first chunk of data and reason of the cut, end of data is treated as end of input
+    // (no copying, chunker state is not touched);
+    // CutReason::Eof must be returned if more data after the end could move the cut
+    fn find_cut(&self, data: &[u8]) -> (usize, CutReason);
+
+    // find_cut for chunkers which cuts depend on position in input: state is given by the cut
+    // of previous chunk (0 at the beginning of input), state after the cut is returned too
+    // and isn't used after Eof cut
+    fn find_cut_with_state(&self, data: &[u8], state: u64) -> (usize, CutReason, u64) {
+        let (length, reason) = self.find_cut(data);
+        (length, reason, state)
+    }
+
+    // input buffered between next_chunk calls
+    fn stream(&mut self) -> &mut ChunkStream;
+
+    // writes next chunk of input to output, last chunk is the one with CutReason::Eof (it may be empty)
     fn next_chunk(
         &mut self,
         input: &mut dyn Read,
         output: &mut dyn Write,
-    ) -> Result<ChunkerStatus, ChunkerError>;
+    ) -> Result<ChunkInfo, ChunkerError> {
+        // stream is taken out for a moment, so chunker itself can be borrowed for find_cut
+        let mut stream = std::mem::take(self.stream());
+        let result = stream.next_chunk(self, input, output);
+        *self.stream() = stream;
+        result
+    }
+
+    // compute SHA-256 of every chunk given by next_chunk
+    fn set_strong_hash(&mut self, enabled: bool) {
+        self.stream().strong_hash = enabled;
+    }
+
+    // chunks of data, same bounds as next_chunk gives for it
+    fn boundaries<'a>(&'a self, data: &'a [u8]) -> Boundaries<'a, Self>
+    where
+        Self: Sized,
+    {
+        Boundaries::new(self, data)
+    }
 
     fn read_file(&self, path: &str) -> Result<Vec<u8>, Error> {
         let mut f = File::open(path)?;
@@ -36,3 +110,197 @@ pub trait Chunker {
         Ok(buffer)
     }
 }
+
+// zero-copy iterator over chunks in slice
+pub struct Boundaries<'a, C: ?Sized> {
+    chunker: &'a C,
+    data: &'a [u8],
+    offset: usize,      // start of next chunk
+    state: u64,         // state of chunker at next chunk
+    strong_hash: bool,  // compute SHA-256 of every chunk
+}
+
+impl<'a, C: Chunker + ?Sized> Boundaries<'a, C> {
+    pub fn new(chunker: &'a C, data: &'a [u8]) -> Self {
+        Boundaries {
+            chunker,
+            data,
+            offset: 0,
+            state: 0,
+            strong_hash: false,
+        }
+    }
+
+    pub fn with_strong_hash(mut self) -> Self {
+        self.strong_hash = true;
+        self
+    }
+}
+
+impl<C: Chunker + ?Sized> Iterator for Boundaries<'_, C> {
+    type Item = ChunkInfo;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = &self.data[self.offset..];
+        if rest.is_empty() {
+            return None;
+        }
+
+        let (length, reason, state) = self.chunker.find_cut_with_state(rest, self.state);
+        let info = ChunkInfo {
+            offset: self.offset as u64,
+            length,
+            reason,
+            hash: self.strong_hash.then(|| strong_hash(&rest[..length])),
+        };
+        self.offset += length;
+        self.state = state;
+        Some(info)
+    }
+}
+
+// buffered reading for next_chunk: bytes of current chunk stay in buffer until the cut is found,
+// buffer grows if chunk doesn't fit in it, so chunk size is not limited by buffer size
+pub struct ChunkStream {
+    buffer: Vec<u8>,
+    buffer_size: usize, // initial size of buffer, allocated on first read
+    start: usize,       // start of current chunk in buffer
+    end: usize,         // end of read data in buffer
+    offset: u64,        // absolute offset of current chunk
+    state: u64,         // state of chunker at current chunk
+    eof: bool,          // end of input reached
+    strong_hash: bool,  // compute SHA-256 of every chunk
+}
+
+impl Default for ChunkStream {
+    fn default() -> Self {
+        ChunkStream::new(DEFAULT_BUFFER_SIZE)
+    }
+}
+
+pub const DEFAULT_BUFFER_SIZE: usize = 4096;
+
+impl ChunkStream {
+    pub fn new(buffer_size: usize) -> ChunkStream {
+        ChunkStream {
+            buffer: Vec::new(),
+            buffer_size: buffer_size.max(1),
+            start: 0,
+            end: 0,
+            offset: 0,
+            state: 0,
+            eof: false,
+            strong_hash: false,
+        }
+    }
+
+    pub fn next_chunk<C: Chunker + ?Sized>(
+        &mut self,
+        chunker: &C,
+        input: &mut dyn Read,
+        output: &mut dyn Write,
+    ) -> Result<ChunkInfo, ChunkerError> {
+        loop {
+            let pending = &self.buffer[self.start..self.end];
+            if !pending.is_empty() || self.eof {
+                let (length, reason, state) = if pending.is_empty() {
+                    (0, CutReason::Eof, self.state)
+                } else {
+                    chunker.find_cut_with_state(pending, self.state)
+                };
+
+                // cut by end of buffer is a real cut only at the end of input
+                if reason != CutReason::Eof || self.eof {
+                    let chunk = &pending[..length];
+                    output.write_all(chunk).map_err(ChunkerError::Write)?;
+
+                    let info = ChunkInfo {
+                        offset: self.offset,
+                        length,
+                        reason,
+                        hash: self.strong_hash.then(|| strong_hash(chunk)),
+                    };
+                    self.start += length;
+                    self.offset += length as u64;
+                    self.state = state;
+                    return Ok(info);
+                }
+            }
+
+            self.fill(input)?;
+        }
+    }
+
+    // read more data after current chunk, buffer at least doubles unscanned data
+    // so every byte is scanned O(1) times on average
+    fn fill(&mut self, input: &mut dyn Read) -> Result<(), ChunkerError> {
+        // move unprocessed data to the beginning of buffer
+        self.buffer.copy_within(self.start..self.end, 0);
+        self.end -= self.start;
+        self.start = 0;
+
+        if self.end * 2 > self.buffer.len() || self.buffer.is_empty() {
+            let size = (self.buffer.len() * 2).max(self.buffer_size);
+            self.buffer.resize(size, 0);
+        }
+
+        while self.end < self.buffer.len() {
+            let read = input
+                .read(&mut self.buffer[self.end..])
+                .map_err(ChunkerError::Read)?;
+
+            // end of input reached
+            if read == 0 {
+                self.eof = true;
+                break;
+            }
+            self.end += read;
+        }
+
+        Ok(())
+    }
+}
+
+impl<C: Chunker + ?Sized> Chunker for &mut C {
+    fn find_cut(&self, data: &[u8]) -> (usize, CutReason) {
+        (**self).find_cut(data)
+    }
+
+    fn find_cut_with_state(&self, data: &[u8], state: u64) -> (usize, CutReason, u64) {
+        (**self).find_cut_with_state(data, state)
+    }
+
+    fn stream(&mut self) -> &mut ChunkStream {
+        (**self).stream()
+    }
+
+    fn next_chunk(
+        &mut self,
+        input: &mut dyn Read,
+        output: &mut dyn Write,
+    ) -> Result<ChunkInfo, ChunkerError> {
+        (**self).next_chunk(input, output)
+    }
+}
+
+impl<C: Chunker + ?Sized> Chunker for Box<C> {
+    fn find_cut(&self, data: &[u8]) -> (usize, CutReason) {
+        (**self).find_cut(data)
+    }
+
+    fn find_cut_with_state(&self, data: &[u8], state: u64) -> (usize, CutReason, u64) {
+        (**self).find_cut_with_state(data, state)
+    }
+
+    fn stream(&mut self) -> &mut ChunkStream {
+        (**self).stream()
+    }
+
+    fn next_chunk(
+        &mut self,
+        input: &mut dyn Read,
+        output: &mut dyn Write,
+    ) -> Result<ChunkInfo, ChunkerError> {
+        (**self).next_chunk(input, output)
+    }
+}
@@ -1,53 +1,39 @@
 use super::chunker::*;
-use std::cmp::min;
-use std::io::{Read, Write};
 
 pub struct FixedChunker {
-    buffer: [u8; 4096],
+    stream: ChunkStream,
     chunk_size: usize,
 }
 
 impl FixedChunker {
-    pub fn new(expected_size: usize) -> FixedChunker {
-        FixedChunker {
-            buffer: [0; 4096],
-            chunk_size: expected_size,
+    pub fn new(expected_size: usize) -> Result<FixedChunker, ChunkerError> {
+        if expected_size == 0 {
+            return Err(ChunkerError::New("chunk size must be positive"));
         }
+
+        Ok(FixedChunker {
+            stream: ChunkStream::default(),
+            chunk_size: expected_size,
+        })
+    }
+
+    // initial size of buffer for reading from input
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.stream = ChunkStream::new(buffer_size);
+        self
     }
 }
 
 impl Chunker for FixedChunker {
-    fn next_chunk(
-        &mut self,
-        input: &mut dyn Read,
-        output: &mut dyn Write,
-    ) -> Result<ChunkerStatus, ChunkerError> {
-        // remaind to read
-        let mut remainder = self.chunk_size;
-        loop {
-            // if chunk_size of remainder > chunk_size of buffer then at first read buffer chunk_size data
-            let to_read = min(remainder, self.buffer.len());
-            let read = input
-                .read(&mut self.buffer[..to_read])
-                .map_err(ChunkerError::Read)?;
-
-            // end of input reached
-            if read == 0 {
-                return Ok(ChunkerStatus::Finished);
-            }
-
-            // write processed data
-            output
-                .write_all(&self.buffer[..read])
-                .map_err(ChunkerError::Write)?;
-
-            if read > remainder {
-                return Ok(ChunkerStatus::Finished);
-            }
-            remainder -= read;
-            if remainder == 0 {
-                return Ok(ChunkerStatus::Working);
-            }
+    fn find_cut(&self, data: &[u8]) -> (usize, CutReason) {
+        if data.len() >= self.chunk_size {
+            (self.chunk_size, CutReason::MaxSize)
+        } else {
+            (data.len(), CutReason::Eof)
         }
     }
+
+    fn stream(&mut self) -> &mut ChunkStream {
+        &mut self.stream
+    }
 }
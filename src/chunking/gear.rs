@@ -0,0 +1,125 @@
+use super::chunker::*;
+use super::hash::{generate_masks, generate_seq};
+
+// "Ddelta: A deduplication-inspired fast delta compression approach"
+// gear hash with one mask and min/max guards, FastCDC without normalization
+
+// parameters of GearChunker
+#[derive(Clone, Debug)]
+pub struct GearConfig {
+    min_size: usize,
+    expected_size: usize,
+    max_size: usize,
+    seed: u64,                // seed for gear table and mask
+    gear: Option<[u64; 256]>, // own gear table instead of generated from seed
+    buffer_size: usize,       // initial size of buffer for reading from input
+}
+
+impl GearConfig {
+    pub fn new(expected_size: usize) -> GearConfig {
+        GearConfig {
+            min_size: expected_size / 4,
+            expected_size,
+            max_size: expected_size.saturating_mul(8),
+            seed: 0,
+            gear: None,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+        }
+    }
+
+    pub fn min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn gear(mut self, gear: [u64; 256]) -> Self {
+        self.gear = Some(gear);
+        self
+    }
+
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    pub fn build(self) -> Result<GearChunker, ChunkerError> {
+        if self.expected_size == 0 {
+            return Err(ChunkerError::New("expected size must be positive"));
+        }
+        if self.expected_size > 1 << 63 {
+            return Err(ChunkerError::New("expected size is too big"));
+        }
+        if self.min_size > self.expected_size || self.expected_size > self.max_size {
+            return Err(ChunkerError::New(
+                "sizes must satisfy min <= expected <= max",
+            ));
+        }
+        if self.buffer_size == 0 {
+            return Err(ChunkerError::New("buffer size must be positive"));
+        }
+
+        // without normalization short and long masks are the same
+        let (mask, _) = generate_masks(self.expected_size, 0, self.seed);
+        Ok(GearChunker {
+            stream: ChunkStream::new(self.buffer_size),
+            gear: self.gear.unwrap_or_else(|| generate_seq(self.seed)),
+            min_size: self.min_size,
+            max_size: self.max_size,
+            mask,
+            config: self,
+        })
+    }
+}
+
+pub struct GearChunker {
+    stream: ChunkStream, // for buffered reading from input
+    gear: [u64; 256],    // random value for every byte
+    min_size: usize,     // hash is not computed for first min_size bytes of chunk
+    max_size: usize,     // max size of chunk
+    mask: u64,           // chunk is cut when hash & mask == 0
+    config: GearConfig,  // parameters chunker was built with
+}
+
+impl GearChunker {
+    pub fn new(expected_size: usize, seed: u64) -> Result<GearChunker, ChunkerError> {
+        GearConfig::new(expected_size).seed(seed).build()
+    }
+
+    pub fn config(&self) -> &GearConfig {
+        &self.config
+    }
+}
+
+impl Chunker for GearChunker {
+    fn find_cut(&self, data: &[u8]) -> (usize, CutReason) {
+        let mut hash = 0u64;
+        let end = data.len().min(self.max_size);
+        for (i, &cur_val) in data[..end].iter().enumerate().skip(self.min_size) {
+            hash = (hash << 1).wrapping_add(self.gear[cur_val as usize]);
+
+            if hash & self.mask == 0 {
+                return (i + 1, CutReason::Hash);
+            }
+        }
+
+        if data.len() >= self.max_size {
+            (self.max_size, CutReason::MaxSize)
+        } else {
+            (data.len(), CutReason::Eof)
+        }
+    }
+
+    fn stream(&mut self) -> &mut ChunkStream {
+        &mut self.stream
+    }
+}
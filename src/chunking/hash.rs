@@ -0,0 +1,104 @@
+// helpers for hash tables and masks shared by chunkers
+
+// https://stackoverflow.com/questions/66168970/find-more-indipendent-seed-value-for-a-64-bit-lcg-mmix-by-knuth
+// random numbers for every byte value: 0..256
+pub fn generate_seq(seed: u64) -> [u64; 256] {
+    let mut result = [0u64; 256];
+    let alpha = 6364136223846793005;
+    let gamma = 1442695040888963407;
+
+    let mut cur_value = seed;
+    for element in &mut result.iter_mut() {
+        cur_value = cur_value.wrapping_mul(alpha).wrapping_add(gamma);
+        *element = cur_value;
+    }
+
+    result
+}
+
+// pseudo-random bytes from the same generator, for tests and benchmarks
+pub fn random_data(len: usize, seed: u64) -> Vec<u8> {
+    let mut cur_value = seed;
+    (0..len)
+        .map(|_| {
+            cur_value = cur_value
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (cur_value >> 56) as u8
+        })
+        .collect()
+}
+
+// masks of reference FastCDC 2020 for every bits count (destor and restic values)
+pub const MASKS_2020: [u64; 26] = [
+    0,                  // padding
+    0,                  // padding
+    0,                  // padding
+    0,                  // padding
+    0,                  // padding
+    0x0000000001804110, // unused except for NC 3
+    0x0000000001803110, // 64B
+    0x0000000018035100, // 128B
+    0x0000001800035300, // 256B
+    0x0000019000353000, // 512B
+    0x0000590003530000, // 1KB
+    0x0000d90003530000, // 2KB
+    0x0000d90103530000, // 4KB
+    0x0000d90303530000, // 8KB
+    0x0000d90313530000, // 16KB
+    0x0000d90f03530000, // 32KB
+    0x0000d90303537000, // 64KB
+    0x0000d90703537000, // 128KB
+    0x0000d90707537000, // 256KB
+    0x0000d91707537000, // 512KB
+    0x0000d91747537000, // 1MB
+    0x0000d91767537000, // 2MB
+    0x0000d93767537000, // 4MB
+    0x0000d93777537000, // 8MB
+    0x0000d93777577000, // 16MB
+    0x0000db3777577000, // unused except for NC 3
+];
+
+// (short, long) masks with bits_count + noice and bits_count - noice bits, where bits_count is
+// bits count of expected size; generated long mask is a subset of short one
+pub fn generate_masks(expected_size: usize, noice: usize, seed: u64) -> (u64, u64) {
+    let bits_count = (expected_size.next_power_of_two() - 1).count_ones();
+    // masks from paper for all averages from 64 B to 16 MB, so for 256 B - 4 MB with levels 0-3
+    if bits_count as usize >= noice + 5 && bits_count as usize + noice < MASKS_2020.len() {
+        let bits_count = bits_count as usize;
+        return (
+            MASKS_2020[bits_count + noice],
+            MASKS_2020[bits_count - noice],
+        );
+    }
+
+    let mut mask = 0u64;
+    let mut cur_value = seed;
+    let alpha = 6364136223846793005;
+    let gamma = 1442695040888963407;
+
+    while mask.count_ones() < bits_count - noice as u32 {
+        cur_value = cur_value.wrapping_mul(alpha).wrapping_add(gamma);
+        mask = (mask | 1).rotate_left(cur_value as u32 & 0x3f);
+    }
+    let long_mask = mask;
+
+    while mask.count_ones() < bits_count + noice as u32 {
+        cur_value = cur_value.wrapping_mul(alpha).wrapping_add(gamma);
+        mask = (mask | 1).rotate_left(cur_value as u32 & 0x3f);
+    }
+    let short_mask = mask;
+
+    (short_mask, long_mask)
+}
+
+// (short, long) thresholds for hash, hash < threshold has probability 1 / expected_size
+// divided and multiplied by 2^noice, like masks with fractional bits count
+pub fn generate_thresholds(expected_size: usize, noice: usize) -> (u64, u64) {
+    let threshold = (1u128 << 64) / expected_size as u128;
+
+    (
+        (threshold >> noice) as u64,
+        (threshold << noice).min(u64::MAX as u128) as u64,
+    )
+}
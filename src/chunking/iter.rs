@@ -0,0 +1,58 @@
+use super::chunker::*;
+use std::io::Read;
+
+// chunk with its data
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Chunk {
+    pub info: ChunkInfo,
+    pub data: Vec<u8>,
+}
+
+// lazy iterator over chunks of input, only one chunk is held in memory at a time
+pub struct ChunkIter<C, R> {
+    chunker: C,
+    input: R,
+    finished: bool, // last chunk or error was already given
+}
+
+impl<C: Chunker, R: Read> ChunkIter<C, R> {
+    pub fn new(chunker: C, input: R) -> ChunkIter<C, R> {
+        ChunkIter {
+            chunker,
+            input,
+            finished: false,
+        }
+    }
+
+    pub fn into_inner(self) -> (C, R) {
+        (self.chunker, self.input)
+    }
+}
+
+impl<C: Chunker, R: Read> Iterator for ChunkIter<C, R> {
+    type Item = Result<Chunk, ChunkerError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        let mut data = Vec::new();
+        match self.chunker.next_chunk(&mut self.input, &mut data) {
+            Ok(info) => {
+                if info.reason == CutReason::Eof {
+                    self.finished = true;
+                    // input ended right after previous chunk
+                    if info.length == 0 {
+                        return None;
+                    }
+                }
+                Some(Ok(Chunk { info, data }))
+            }
+            Err(err) => {
+                self.finished = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
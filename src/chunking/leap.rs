@@ -0,0 +1,192 @@
+use super::chunker::*;
+use super::hash::generate_seq;
+
+// "Leap-based Content Defined Chunking --- Theory and Implementation"
+// position is a cut point when all windows_count windows ending at it and before it pass judgment;
+// when a window fails, no position up to windows_count - 1 bytes after its end can be a cut point,
+// so the search leaps forward over them
+
+// parameters of LeapCdcChunker
+#[derive(Clone, Debug)]
+pub struct LeapCdcConfig {
+    min_size: usize,
+    expected_size: usize,
+    max_size: usize,
+    window_size: usize,   // size of judged window
+    windows_count: usize, // number of windows which must pass judgment
+    seed: u64,            // seed for table of judgment function
+    buffer_size: usize,   // initial size of buffer for reading from input
+}
+
+impl LeapCdcConfig {
+    pub fn new(expected_size: usize) -> LeapCdcConfig {
+        LeapCdcConfig {
+            min_size: expected_size / 4,
+            expected_size,
+            max_size: expected_size.saturating_mul(4),
+            // values from paper
+            window_size: 24,
+            windows_count: 24,
+            seed: 0,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+        }
+    }
+
+    pub fn min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    pub fn window_size(mut self, window_size: usize) -> Self {
+        self.window_size = window_size;
+        self
+    }
+
+    pub fn windows_count(mut self, windows_count: usize) -> Self {
+        self.windows_count = windows_count;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    // probability q of window to pass judgment, such that mean distance to windows_count passed
+    // windows in a row, (q^-k - 1) / (1 - q), is expected_size - min_size
+    fn pass_probability(&self) -> f64 {
+        let target = (self.expected_size - self.min_size).max(1) as f64;
+        let distance = |q: f64| (q.powi(-(self.windows_count as i32)) - 1.) / (1. - q);
+
+        // distance decreases with q
+        let (mut low, mut high) = (0f64, 1f64);
+        for _ in 0..100 {
+            let q = (low + high) / 2.;
+            if distance(q) > target {
+                low = q;
+            } else {
+                high = q;
+            }
+        }
+
+        low
+    }
+
+    pub fn build(self) -> Result<LeapCdcChunker, ChunkerError> {
+        if self.expected_size == 0 {
+            return Err(ChunkerError::New("expected size must be positive"));
+        }
+        if self.min_size > self.expected_size || self.expected_size > self.max_size {
+            return Err(ChunkerError::New(
+                "sizes must satisfy min <= expected <= max",
+            ));
+        }
+        if self.window_size == 0 || self.windows_count == 0 {
+            return Err(ChunkerError::New(
+                "window size and windows count must be positive",
+            ));
+        }
+        if self.windows_count > i32::MAX as usize {
+            return Err(ChunkerError::New("windows count is too big"));
+        }
+        // positions up to max_size + window_size + windows_count are computed in find_cut
+        let bound = self.max_size.checked_add(self.window_size);
+        if bound
+            .and_then(|size| size.checked_add(self.windows_count))
+            .is_none()
+        {
+            return Err(ChunkerError::New(
+                "window size and windows count are too big",
+            ));
+        }
+        if self.buffer_size == 0 {
+            return Err(ChunkerError::New("buffer size must be positive"));
+        }
+
+        Ok(LeapCdcChunker {
+            stream: ChunkStream::new(self.buffer_size),
+            table: generate_seq(self.seed),
+            threshold: (self.pass_probability() * u64::MAX as f64) as u64,
+            window_size: self.window_size,
+            windows_count: self.windows_count,
+            min_size: self.min_size,
+            max_size: self.max_size,
+            config: self,
+        })
+    }
+}
+
+pub struct LeapCdcChunker {
+    stream: ChunkStream,   // for buffered reading from input
+    table: [u64; 256],     // random value for every byte
+    threshold: u64,        // window passes judgment when its hash is less than threshold
+    window_size: usize,    // size of judged window
+    windows_count: usize,  // number of windows which must pass judgment
+    min_size: usize,       // min size of chunk
+    max_size: usize,       // max size of chunk
+    config: LeapCdcConfig, // parameters chunker was built with
+}
+
+impl LeapCdcChunker {
+    pub fn new(expected_size: usize, seed: u64) -> Result<LeapCdcChunker, ChunkerError> {
+        LeapCdcConfig::new(expected_size).seed(seed).build()
+    }
+
+    pub fn config(&self) -> &LeapCdcConfig {
+        &self.config
+    }
+
+    // pseudo-random function of window bytes, windows differing in one byte get unrelated hashes
+    fn judge(&self, window: &[u8]) -> bool {
+        let mut hash = window.iter().fold(0u64, |hash, &cur_val| {
+            (hash ^ self.table[cur_val as usize]).wrapping_mul(0x100000001b3)
+        });
+        // splitmix64 finalizer
+        hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d049bb133111eb);
+        hash ^= hash >> 31;
+
+        hash < self.threshold
+    }
+}
+
+impl Chunker for LeapCdcChunker {
+    fn find_cut(&self, data: &[u8]) -> (usize, CutReason) {
+        let limit = data.len().min(self.max_size);
+        // chunk ends with byte at pos and every window ending at or before pos is inside chunk
+        let mut pos =
+            (self.window_size + self.windows_count - 2).max(self.min_size.saturating_sub(1));
+        'candidates: while pos < limit {
+            // nearest window is checked first, so its failure gives the longest leap
+            for i in 0..self.windows_count {
+                let end = pos - i + 1;
+                if !self.judge(&data[end - self.window_size..end]) {
+                    pos += self.windows_count - i;
+                    continue 'candidates;
+                }
+            }
+
+            return (pos + 1, CutReason::Hash);
+        }
+
+        if data.len() >= self.max_size {
+            (self.max_size, CutReason::MaxSize)
+        } else {
+            (data.len(), CutReason::Eof)
+        }
+    }
+
+    fn stream(&mut self) -> &mut ChunkStream {
+        &mut self.stream
+    }
+}
@@ -0,0 +1,158 @@
+use super::chunker::*;
+use super::parallel;
+use std::time::Duration;
+
+// parameters of MaxpChunker
+#[derive(Clone, Debug)]
+pub struct MaxpConfig {
+    min_size: usize,
+    expected_size: usize,
+    max_size: usize,
+    window_size: usize, // number of values on every side of extremum
+    buffer_size: usize, // initial size of buffer for reading from input
+}
+
+impl MaxpConfig {
+    pub fn new(expected_size: usize) -> MaxpConfig {
+        MaxpConfig {
+            min_size: expected_size / 4,
+            expected_size,
+            max_size: expected_size.saturating_mul(4),
+            // with min size expected / 4 this gives mean chunk ~expected on random data
+            window_size: (expected_size / 2).max(1),
+            buffer_size: DEFAULT_BUFFER_SIZE,
+        }
+    }
+
+    pub fn min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    pub fn window_size(mut self, window_size: usize) -> Self {
+        self.window_size = window_size;
+        self
+    }
+
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    pub fn build(self) -> Result<MaxpChunker, ChunkerError> {
+        if self.expected_size == 0 {
+            return Err(ChunkerError::New("expected size must be positive"));
+        }
+        if self.min_size > self.expected_size || self.expected_size > self.max_size {
+            return Err(ChunkerError::New(
+                "sizes must satisfy min <= expected <= max",
+            ));
+        }
+        if self.window_size == 0 {
+            return Err(ChunkerError::New("window size must be positive"));
+        }
+        // positions up to max_size + window_size are computed in find_cut
+        let bound = self.max_size.checked_add(self.window_size);
+        if bound
+            .and_then(|size| size.checked_add(VALUE_SIZE))
+            .is_none()
+        {
+            return Err(ChunkerError::New("window size is too big"));
+        }
+        if self.buffer_size == 0 {
+            return Err(ChunkerError::New("buffer size must be positive"));
+        }
+
+        Ok(MaxpChunker {
+            stream: ChunkStream::new(self.buffer_size),
+            window_size: self.window_size,
+            min_size: self.min_size,
+            max_size: self.max_size,
+            config: self,
+        })
+    }
+}
+
+// value at position is 8 bytes ending there, single bytes repeat in windows over ~256 bytes
+// and strict maximum would be rare
+const VALUE_SIZE: usize = 8;
+
+fn value_at(data: &[u8], pos: usize) -> u64 {
+    let mut bytes = [0u8; VALUE_SIZE];
+    bytes.copy_from_slice(&data[pos + 1 - VALUE_SIZE..=pos]);
+    u64::from_be_bytes(bytes)
+}
+
+// "Content-dependent chunking for differential compression, the local maximum approach"
+// chunk ends at value which is strictly greater than window_size values on both its sides
+pub struct MaxpChunker {
+    stream: ChunkStream, // for buffered reading from input
+    window_size: usize,  // number of values on every side of extremum
+    min_size: usize,     // min size of chunk
+    max_size: usize,     // max size of chunk, values after it are not compared
+    config: MaxpConfig,  // parameters chunker was built with
+}
+
+impl MaxpChunker {
+    pub fn new(expected_size: usize) -> Result<MaxpChunker, ChunkerError> {
+        MaxpConfig::new(expected_size).build()
+    }
+
+    pub fn config(&self) -> &MaxpConfig {
+        &self.config
+    }
+
+    pub fn parallel_chunking(
+        &self,
+        path: &str,
+        threads_cnt: usize,
+    ) -> Result<(Duration, f64, f64), ChunkerError> {
+        parallel::parallel_chunking(self, path, threads_cnt)
+    }
+}
+
+impl Chunker for MaxpChunker {
+    fn find_cut(&self, data: &[u8]) -> (usize, CutReason) {
+        let limit = data.len().min(self.max_size);
+        // left window of candidate is inside chunk and chunk is not smaller than min_size
+        let mut pos = (self.window_size + VALUE_SIZE - 1).max(self.min_size.saturating_sub(1));
+        while pos < limit {
+            let cur_val = value_at(data, pos);
+            // nearest values are the most likely to be greater
+            if (pos - self.window_size..pos)
+                .rev()
+                .any(|i| value_at(data, i) >= cur_val)
+            {
+                pos += 1;
+                continue;
+            }
+
+            // right window is cut by max size of chunk
+            let right_end = (pos + self.window_size + 1).min(limit);
+            match (pos + 1..right_end).find(|&i| value_at(data, i) >= cur_val) {
+                // values between are less than cur_val which is in their left windows
+                Some(next_pos) => pos = next_pos,
+                None if right_end == pos + self.window_size + 1 || limit == self.max_size => {
+                    return (pos + 1, CutReason::Hash);
+                }
+                // more data is needed to check right window
+                None => return (data.len(), CutReason::Eof),
+            }
+        }
+
+        if data.len() >= self.max_size {
+            (self.max_size, CutReason::MaxSize)
+        } else {
+            (data.len(), CutReason::Eof)
+        }
+    }
+
+    fn stream(&mut self) -> &mut ChunkStream {
+        &mut self.stream
+    }
+}
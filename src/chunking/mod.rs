@@ -1,5 +1,33 @@
+#[cfg(feature = "ae")]
 pub mod ae;
+#[cfg(feature = "buzhash")]
+pub mod buzhash;
 pub mod chunker;
+#[cfg(feature = "fastcdc")]
 pub mod fastcdc;
+#[cfg(feature = "fixed")]
 pub mod fixed;
+#[cfg(feature = "gear")]
+pub mod gear;
+pub mod hash;
+pub mod iter;
+#[cfg(feature = "leap")]
+pub mod leap;
+#[cfg(feature = "maxp")]
+pub mod maxp;
+pub mod parallel;
+#[cfg(feature = "pci")]
+pub mod pci;
+#[cfg(feature = "rabin")]
 pub mod rabin;
+#[cfg(feature = "ram")]
+pub mod ram;
+#[cfg(feature = "rsync")]
+pub mod rsync;
+#[cfg(feature = "tar")]
+pub mod tar;
+#[cfg(feature = "tttd")]
+pub mod tttd;
+
+#[cfg(test)]
+mod tests;
@@ -1,155 +1,140 @@
-use rayon::prelude::*;
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
 
+use super::chunker::*;
 use std::collections::hash_map::DefaultHasher;
-
-fn read_file(path: &str) -> Result<Vec<u8>, Error> {
-    let mut f = File::open(path)?;
-    let mut buffer = Vec::new();
-    f.read_to_end(&mut buffer)?;
-
-    Ok(buffer)
-}
-
-fn hash_vec<T: Hash>(v: &Vec<T>) -> u64 {
-    let mut hasher = DefaultHasher::new();
-    v.hash(&mut hasher);
-    hasher.finish()
-}
-
-fn preprocess_vec(buffer: &Vec<u8>, threads_cnt: usize) -> Vec<Vec<u8>> {
-    let mut vecs: Vec<Vec<u8>> = vec![Vec::new(); threads_cnt];
-    for i in 0..threads_cnt {
-        let left = i * buffer.len() / threads_cnt;
-        let right = (i + 1) * buffer.len() / threads_cnt;
-        vecs[i] = (buffer[left..right]).to_vec();
-    }
-    return vecs;
-}
-
-fn work_for_thread(i: usize, n: usize, cur: &Vec<u8>) -> (HashSet<u64>, usize, Vec<u8>) {
-    let mut chunker = AeChunker::new(1024 * n);
-    // let mut chunker = FastCdcChunker::new(1024 * n, 1);
-    // let mut chunker = RabinChunker::new(1024 * n, 0);
-    let mut cursor = Cursor::new(cur);
-    let mut set = HashSet::new();
-    let mut result = 0;
-
-    let vecs = test_chunking(&mut chunker, &mut cursor);
-    let left = if i % 2 == 0 { 0 } else { 1 };
-    let right = if i % 2 == 0 {
-        vecs.len() - 1
-    } else {
-        vecs.len()
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, Instant};
+
+// chunk of data starting at pos with given state of chunker, as sequential chunking would cut it,
+// and state after it
+fn chunk_at<C: Chunker + ?Sized>(
+    chunker: &C,
+    data: &[u8],
+    pos: usize,
+    state: u64,
+) -> (ChunkInfo, u64) {
+    let (length, reason, state) = chunker.find_cut_with_state(&data[pos..], state);
+    let info = ChunkInfo {
+        offset: pos as u64,
+        length,
+        reason,
+        hash: None,
     };
+    (info, state)
+}
 
-    for i in left..right {
-        let hsh = hash_vec(&vecs[i]);
-        if !set.contains(&hsh) {
-            set.insert(hsh);
-            result += vecs[i].len();
-        }
+// chunks from start of segment as if chunking started there, last one ends at or after end of segment;
+// (chunks with states of chunker at them, state after the last one)
+fn scan_segment<C: Chunker + ?Sized>(
+    chunker: &C,
+    data: &[u8],
+    left: usize,
+    right: usize,
+) -> (Vec<(ChunkInfo, u64)>, u64) {
+    let mut result = Vec::new();
+    let mut pos = left;
+    let mut state = 0;
+    while pos < right {
+        let (info, next_state) = chunk_at(chunker, data, pos, state);
+        pos += info.length;
+        result.push((info, state));
+        state = next_state;
     }
 
-    let remainder = if i % 2 == 0 {
-        vecs.last()
-    } else {
-        vecs.first()
-    };
-    (set, result, remainder.unwrap().clone())
+    (result, state)
 }
 
-fn collect_results(
-    result_set: &mut HashSet<u64>,
-    mut ans: usize,
-    remainders: &mut Vec<Vec<u8>>,
-    n: usize,
-) -> (usize, usize) {
-    let mut i = 0;
-    while i < remainders.len() - 1 {
-        let mut t = remainders[i + 1].clone();
-        remainders[i].append(&mut t);
-        let mut chunker = AeChunker::new(1024 * n);
-        // let mut chunker = FastCdcChunker::new(1024 * n, 1);
-        // let mut chunker = RabinChunker::new(1024 * n, 0);
-        let mut cursor = Cursor::new(&remainders[i]);
-        for elem in test_chunking(&mut chunker, &mut cursor) {
-            let hsh = hash_vec(&elem);
-            if !result_set.contains(&hsh) {
-                result_set.insert(hsh);
-                ans += elem.len();
+// same chunks as sequential chunking of data gives, segments of data are chunked in parallel
+//
+// chunking of every segment starts from its beginning, so its first chunks may differ from sequential ones;
+// segments are joined from left to right: sequential chunking continues after the end of joined chunks
+// until a chunk starts at a bound of the next segment with the same state of chunker, all bounds of it
+// after that one are the same as sequential ones; every byte is chunked at most once by the joining,
+// even if bounds never meet
+pub fn parallel_boundaries<C: Chunker + Sync + ?Sized>(
+    chunker: &C,
+    data: &[u8],
+    threads_cnt: usize,
+) -> Vec<ChunkInfo> {
+    let threads_cnt = threads_cnt.max(1);
+    let segments: Vec<(usize, usize)> = (0..threads_cnt)
+        .map(|i| (i * data.len() / threads_cnt, (i + 1) * data.len() / threads_cnt))
+        .collect();
+    let chains: Vec<(Vec<(ChunkInfo, u64)>, u64)> = segments
+        .clone()
+        .into_par_iter()
+        .map(|(left, right)| scan_segment(chunker, data, left, right))
+        .collect();
+
+    let mut result = Vec::new();
+    let mut pos = 0;
+    let mut state = 0;
+    for ((chain, end_state), &(_, right)) in chains.iter().zip(&segments) {
+        while pos < right {
+            let found = chain
+                .binary_search_by_key(&(pos as u64), |(info, _)| info.offset)
+                .ok()
+                .filter(|&i| chain[i].1 == state);
+            if let Some(i) = found {
+                result.extend(chain[i..].iter().map(|(info, _)| info.clone()));
+                pos = chain.last().map_or(pos, |(info, _)| info.range().end);
+                state = *end_state;
+                break;
             }
+            let (info, next_state) = chunk_at(chunker, data, pos, state);
+            pos += info.length;
+            state = next_state;
+            result.push(info);
         }
-        i += 2;
     }
 
-    (ans, result_set.len())
+    result
 }
 
-fn threads_test(path: &str, n: usize, threads_cnt: usize) -> (Duration, f64, f64) {
-    let buffer = read_file(path).unwrap();
-    let vecs = preprocess_vec(&buffer, threads_cnt);
-
-    let now = Instant::now();
-    let (len, cnt) = {
-        let mut threads = vec![];
-
-        for i in 0..threads_cnt {
-            let cur = vecs[i].clone();
-            threads.push(thread::spawn(move || work_for_thread(i, n, &cur)));
-        }
+// (part of unique data, average size of unique chunk) for deduplication by chunks
+pub fn dedup_stats(data: &[u8], chunks: &[ChunkInfo]) -> (f64, f64) {
+    let mut set = HashSet::new();
+    let mut unique_size = 0;
+    for info in chunks {
+        let mut hasher = DefaultHasher::new();
+        data[info.range()].hash(&mut hasher);
 
-        let mut result_set: HashSet<u64> = HashSet::new();
-        let mut ans = 0;
-        let mut remainders = vec![];
-        for handle in threads {
-            let (st, x, y) = handle.join().unwrap();
-            result_set.extend(&st);
-            remainders.push(y);
-            ans += x;
+        if set.insert(hasher.finish()) {
+            unique_size += info.length;
         }
+    }
 
-        collect_results(&mut result_set, ans, &mut remainders, n)
-    };
+    (
+        (unique_size as f64) / (data.len() as f64),
+        (data.len() as f64) / (set.len() as f64),
+    )
+}
 
-    let elapsed = now.elapsed();
-    return (
-        elapsed,
-        (len as f64) / (buffer.len() as f64),
-        (len as f64) / (cnt as f64),
-    );
+// (mean, standard deviation) of chunk sizes
+pub fn size_stats(chunks: &[ChunkInfo]) -> (f64, f64) {
+    let count = chunks.len() as f64;
+    let mean = chunks.iter().map(|info| info.length as f64).sum::<f64>() / count;
+    let variance = chunks
+        .iter()
+        .map(|info| (info.length as f64 - mean).powi(2))
+        .sum::<f64>()
+        / count;
+
+    (mean, variance.sqrt())
 }
 
-fn rayon_test(path: &str, n: usize, threads_cnt: usize) -> (Duration, f64, f64) {
-    let buffer = read_file(path).unwrap();
-    let mut vecs = preprocess_vec(&buffer, threads_cnt);
+// (time, part of unique data, average size of unique chunk) for parallel chunking of file
+pub fn parallel_chunking<C: Chunker + Sync + ?Sized>(
+    chunker: &C,
+    path: &str,
+    threads_cnt: usize,
+) -> Result<(Duration, f64, f64), ChunkerError> {
+    let vec = chunker.read_file(path).map_err(ChunkerError::Read)?;
 
     let now = Instant::now();
-    let (len, cnt) = {
-        let tmp: Vec<(HashSet<u64>, usize, Vec<u8>)> = (0..threads_cnt)
-            .into_par_iter()
-            .map(|i| {
-                let cur = vecs[i].clone();
-                work_for_thread(i, n, &cur)
-            })
-            .collect();
-
-        let mut result_set: HashSet<u64> = HashSet::new();
-        let mut ans = 0;
-        let mut remainders = vec![];
-        for handle in tmp {
-            let (st, x, y) = handle;
-            result_set.extend(&st);
-            remainders.push(y);
-            ans += x;
-        }
-
-        collect_results(&mut result_set, ans, &mut remainders, n)
-    };
+    let chunks = parallel_boundaries(chunker, &vec, threads_cnt);
+    let (coeff, avg) = dedup_stats(&vec, &chunks);
 
-    let elapsed = now.elapsed();
-    return (
-        elapsed,
-        (len as f64) / (buffer.len() as f64),
-        (len as f64) / (cnt as f64),
-    );
+    Ok((now.elapsed(), coeff, avg))
 }
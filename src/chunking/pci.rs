@@ -0,0 +1,184 @@
+use super::chunker::*;
+
+// PCI: chunk is cut after the window which popcount (number of one bits) reaches threshold,
+// popcount is updated for every byte entering and leaving the window
+
+// parameters of PciChunker
+#[derive(Clone, Debug)]
+pub struct PciConfig {
+    min_size: usize,
+    expected_size: usize,
+    max_size: usize,
+    window_size: usize,       // size of window which bits are counted
+    threshold: Option<usize>, // own threshold instead of computed from expected size
+    buffer_size: usize,       // initial size of buffer for reading from input
+}
+
+impl PciConfig {
+    pub fn new(expected_size: usize) -> PciConfig {
+        PciConfig {
+            min_size: expected_size / 4,
+            expected_size,
+            max_size: expected_size.saturating_mul(4),
+            // popcount of bigger window takes more values, so threshold is chosen more precisely
+            window_size: 64,
+            threshold: None,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+        }
+    }
+
+    pub fn min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    pub fn window_size(mut self, window_size: usize) -> Self {
+        self.window_size = window_size;
+        self
+    }
+
+    pub fn threshold(mut self, threshold: usize) -> Self {
+        self.threshold = Some(threshold);
+        self
+    }
+
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    // threshold which popcount reaches once in ~(expected_size - min_size) bytes of random data;
+    // popcounts of neighbour windows are close, so frequency of crossing the threshold from below
+    // is used instead of probability to be over it, and crossings go in bursts of ~2 on random data
+    fn default_threshold(&self) -> usize {
+        let bits = 8 * (self.window_size - 1);
+        // binomial distribution of popcount of bits random bits
+        let binomial = |bits: usize| -> Vec<f64> {
+            let mut ln_binom = 0f64;
+            (0..=bits)
+                .map(|k| {
+                    if k > 0 {
+                        ln_binom += ((bits - k + 1) as f64).ln() - (k as f64).ln();
+                    }
+                    (ln_binom - bits as f64 * std::f64::consts::LN_2).exp()
+                })
+                .collect()
+        };
+        let common = binomial(bits);
+        // distribution function of popcount of byte
+        let byte_cdf: Vec<f64> = binomial(8)
+            .iter()
+            .scan(0., |sum, p| {
+                *sum += p;
+                Some(*sum)
+            })
+            .collect();
+
+        // popcount is below threshold with leaving byte and is not with entering one
+        let target = ((self.expected_size - self.min_size).max(1) as f64 / 2.).ln();
+        let crossing_rate = |threshold: usize| -> f64 {
+            common
+                .iter()
+                .enumerate()
+                .filter(|&(count, _)| count < threshold && threshold <= count + 8)
+                .map(|(count, p)| {
+                    let below = byte_cdf[threshold - count - 1];
+                    p * below * (1. - below)
+                })
+                .sum()
+        };
+        (bits / 2 + 1..=bits + 8)
+            .map(|threshold| (threshold, crossing_rate(threshold)))
+            .filter(|&(_, rate)| rate > 0.)
+            .min_by(|(_, a), (_, b)| {
+                (-a.ln() - target)
+                    .abs()
+                    .total_cmp(&(-b.ln() - target).abs())
+            })
+            .map_or(bits + 8, |(threshold, _)| threshold)
+    }
+
+    pub fn build(self) -> Result<PciChunker, ChunkerError> {
+        if self.expected_size == 0 {
+            return Err(ChunkerError::New("expected size must be positive"));
+        }
+        if self.min_size > self.expected_size || self.expected_size > self.max_size {
+            return Err(ChunkerError::New(
+                "sizes must satisfy min <= expected <= max",
+            ));
+        }
+        if self.window_size == 0 || self.window_size > self.max_size {
+            return Err(ChunkerError::New(
+                "window size must satisfy 0 < window <= max",
+            ));
+        }
+        if self.buffer_size == 0 {
+            return Err(ChunkerError::New("buffer size must be positive"));
+        }
+
+        Ok(PciChunker {
+            stream: ChunkStream::new(self.buffer_size),
+            window_size: self.window_size,
+            threshold: self.threshold.unwrap_or_else(|| self.default_threshold()),
+            min_size: self.min_size,
+            max_size: self.max_size,
+            config: self,
+        })
+    }
+}
+
+pub struct PciChunker {
+    stream: ChunkStream, // for buffered reading from input
+    window_size: usize,  // size of window which bits are counted
+    threshold: usize,    // chunk is cut when popcount of window is not less than threshold
+    min_size: usize,     // min size of chunk
+    max_size: usize,     // max size of chunk
+    config: PciConfig,   // parameters chunker was built with
+}
+
+impl PciChunker {
+    pub fn new(expected_size: usize) -> Result<PciChunker, ChunkerError> {
+        PciConfig::new(expected_size).build()
+    }
+
+    pub fn config(&self) -> &PciConfig {
+        &self.config
+    }
+}
+
+impl Chunker for PciChunker {
+    fn find_cut(&self, data: &[u8]) -> (usize, CutReason) {
+        // window ends at cut, so first cut is not before window_size
+        let first = self.min_size.max(self.window_size);
+        let last = data.len().min(self.max_size);
+
+        if first <= last {
+            let window = &data[first - self.window_size..first];
+            let mut count: usize = window.iter().map(|b| b.count_ones() as usize).sum();
+            for cut in first..=last {
+                if cut > first {
+                    count += data[cut - 1].count_ones() as usize;
+                    count -= data[cut - 1 - self.window_size].count_ones() as usize;
+                }
+                if count >= self.threshold {
+                    return (cut, CutReason::Hash);
+                }
+            }
+        }
+
+        if data.len() >= self.max_size {
+            (self.max_size, CutReason::MaxSize)
+        } else {
+            (data.len(), CutReason::Eof)
+        }
+    }
+
+    fn stream(&mut self) -> &mut ChunkStream {
+        &mut self.stream
+    }
+}
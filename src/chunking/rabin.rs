@@ -1,16 +1,22 @@
-use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use super::chunker::{Chunker, ChunkerError, ChunkStream, CutReason, DEFAULT_BUFFER_SIZE};
+use super::parallel;
+use std::cmp::Ordering;
+use std::time::Duration;
 
-use super::chunker::{Chunker, ChunkerError, ChunkerStatus};
-use std::{
-    collections::{VecDeque, HashSet, hash_map::DefaultHasher},
-    io::{Read, Write},
-    ptr, cmp::max, time::{Instant, Duration}, hash::{Hash, Hasher}, thread,
-};
+// irreducible polynomial of degree 63 used by LBFS
+pub const DEFAULT_POLYNOMIAL: u64 = 0xbfe6b8a5bf378d83;
+
+// kind of hash RabinChunker computes over window
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RabinMode {
+    Multiplicative, // polynomial hash with base alpha modulo 2^32
+    Fingerprint,    // Rabin fingerprint over irreducible polynomial in GF(2), as in LBFS and restic
+}
 
 // struct for storing data of Rabin hash
-struct HashRabin {
+pub(crate) struct HashRabin {
     pow_table: [u32; 256], // alpha ^ (hash_exp * chunk_pos) mod 2^32 for chunk_pos in 0..257
-    mask: u32,
+    pub(crate) mask: u32,
     seed: u32,
     alpha: u32,
 }
@@ -30,187 +36,305 @@ impl HashRabin {
             alpha,
         }
     }
+
+    // hash of window ending at data[i] from hash of window ending at data[i - 1],
+    // window is the data itself, so byte leaving it is data[i - window_size]
+    pub fn roll(&self, hash: u32, data: &[u8], i: usize, window_size: usize) -> u32 {
+        let hash = hash.wrapping_mul(self.alpha).wrapping_add(data[i] as u32);
+        if i >= window_size {
+            hash.wrapping_sub(self.pow_table[data[i - window_size] as usize])
+        } else {
+            hash
+        }
+    }
+
+    pub fn matches(&self, hash: u32, mask: u32) -> bool {
+        ((hash ^ self.seed) & mask) == 0
+    }
 }
 
-pub struct RabinChunker {
-    buffer: [u8; 4096], // for buffered reading from input
-    buffered: usize,    // count of first in buffer already taken bytes
-    window_size: usize, // rabin fingerprint window size
-    min_size: usize,    // min size of chunk
-    max_size: usize,    // max size of chunk
-    hash: HashRabin,    // struct for data of used Rabin hash parameters
+// degree of nonzero polynomial over GF(2)
+fn degree(pol: u128) -> u32 {
+    127 - pol.leading_zeros()
 }
 
-impl RabinChunker {
-    pub fn new(expected_size: usize, seed: u32) -> RabinChunker {
-        let window_size = expected_size / 4 - 1;
-        let min_size = expected_size / 4;
-        let max_size = expected_size * 4;
-        let alpha = 1_664_525;
+// remainder of division of polynomials over GF(2)
+fn pol_mod(mut value: u128, pol: u128) -> u128 {
+    let pol_degree = degree(pol);
+    while value != 0 && degree(value) >= pol_degree {
+        value ^= pol << (degree(value) - pol_degree);
+    }
 
-        RabinChunker {
-            buffer: [0; 4096],
-            buffered: 0,
-            window_size,
-            min_size,
-            max_size,
-            hash: HashRabin::new(expected_size, window_size, alpha, seed),
+    value
+}
+
+// product of polynomials over GF(2) modulo pol
+fn pol_mul_mod(a: u64, b: u64, pol: u64) -> u64 {
+    let mut product = 0u128;
+    for i in 0..64 {
+        if (b >> i) & 1 == 1 {
+            product ^= (a as u128) << i;
         }
     }
 
-    pub fn get_bounds(&self, vec: &Vec<u8>, left: usize, right: usize) -> Vec<usize> {
-        let mut result = Vec::new();
-        let mut hash = 0u32;
-        let start = max(0i64, (left as i64) - (self.window_size as i64)) as usize;
-        let mut last_pos = start;
-        let mut window = VecDeque::with_capacity(self.window_size);
-        for i in start..right {
-            hash = hash
-                .wrapping_mul(self.hash.alpha)
-                .wrapping_add(vec[i] as u32);
-            if i >= last_pos + self.window_size {
-                let front_window = window.pop_front().unwrap();
-                hash = hash.wrapping_sub(self.hash.pow_table[front_window as usize]);
-
-                // next chunk finded
-                if i >= last_pos + self.min_size && ((hash ^ self.hash.seed) & self.hash.mask) == 0
-                {
-                    result.push(i);
-                    window = VecDeque::with_capacity(self.window_size);
-                    last_pos = i - 1;
-                    hash = 0u32;
-                    continue;
-                }
-            }
+    pol_mod(product, pol as u128) as u64
+}
+
+fn pol_gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, pol_mod(a as u128, b as u128) as u64);
+    }
+
+    a
+}
+
+// Ben-Or test: pol is irreducible iff gcd(pol, x^(2^i) - x) = 1 for all i <= degree / 2
+fn is_irreducible(pol: u64) -> bool {
+    let mut power = 2u64; // x^(2^i) mod pol
+    for _ in 0..degree(pol as u128) / 2 {
+        power = pol_mul_mod(power, power, pol);
+        if pol_gcd(pol, power ^ 2) != 1 {
+            return false;
+        }
+    }
 
-            window.push_back(vec[i]);
-        }
-
-        result
-    }
-
-    pub fn parallel_chunking(&self, path: &str, threads_cnt: usize) -> (Duration, f64, f64) {
-        let vec = Chunker::read_file(self, path).unwrap();
-        let sz = vec.len();
-
-        let mut threads = vec![];
-        let now = Instant::now();
-        for i in 1..threads_cnt + 1 {
-            let tmp = vec.clone();
-            let self_clone = RabinChunker::new(self.max_size / 4, self.hash.seed);
-            threads.push(thread::spawn(move || self_clone.get_bounds(&tmp,  (i - 1) * tmp.len() / threads_cnt, tmp.len() / threads_cnt)));
-        }
-
-        let mut last: i64 = -1;
-        let mut set = HashSet::new();
-        let mut local_sm = 0;
-        let mut sm = 0;
-        for handle in threads {
-            for elem in handle.join().unwrap() {
-                if (elem as i64) - last >= (self.window_size as i64) {
-                    let next = &vec[((last + 1) as usize)..elem + 1];
-                    let mut hasher = DefaultHasher::new();
-                    next.hash(&mut hasher);
-                    let hsh = hasher.finish();
-    
-                    if !set.contains(&hsh) {
-                        set.insert(hsh);
-                        local_sm += (elem as i64) - last;
-                    }
-                    sm += (elem as i64) - last;
-                    last = elem as i64;
-                }
+    true
+}
+
+// struct for storing tables of Rabin fingerprint
+struct FingerprintRabin {
+    out_table: [u64; 256], // b * x^(8 * (window_size - 1)) mod pol, removes byte b leaving the window
+    mod_table: [u64; 256], // (b * x^degree mod pol) | (b << degree), reduces hash after shift by byte
+    shift: u32,            // degree - 8, top byte of hash is hash >> shift
+}
+
+impl FingerprintRabin {
+    pub fn new(pol: u64, window_size: usize) -> FingerprintRabin {
+        let pol_degree = degree(pol as u128);
+
+        // x^(8 * (window_size - 1)) mod pol by binary exponentiation
+        let mut window_pow = 1u64;
+        let mut base = pol_mod(1 << 8, pol as u128) as u64;
+        let mut exp = window_size - 1;
+        while exp > 0 {
+            if exp & 1 == 1 {
+                window_pow = pol_mul_mod(window_pow, base, pol);
             }
+            base = pol_mul_mod(base, base, pol);
+            exp >>= 1;
+        }
+
+        let mut out_table = [0u64; 256];
+        let mut mod_table = [0u64; 256];
+        for b in 0..256 {
+            out_table[b] = pol_mul_mod(b as u64, window_pow, pol);
+            // bits over 64 are shifted out of hash anyway
+            mod_table[b] = pol_mod((b as u128) << pol_degree, pol as u128) as u64
+                | ((b as u128) << pol_degree) as u64;
+        }
+
+        FingerprintRabin {
+            out_table,
+            mod_table,
+            shift: pol_degree - 8,
         }
-        
-        let elapsed = now.elapsed();
-        (elapsed, (local_sm as f64) / (sz as f64), (sm as f64) / (set.len() as f64))
+    }
+
+    // front byte leaves the window, back byte enters it
+    fn slide(&self, hash: u64, front: u8, back: u8) -> u64 {
+        let hash = hash ^ self.out_table[front as usize];
+        let index = (hash >> self.shift) as usize;
+        ((hash << 8) | back as u64) ^ self.mod_table[index]
     }
 }
 
-impl Chunker for RabinChunker {
-    fn next_chunk(
-        &mut self,
-        input: &mut dyn Read,
-        output: &mut dyn Write,
-    ) -> Result<ChunkerStatus, ChunkerError> {
+// parameters of RabinChunker
+#[derive(Clone, Debug)]
+pub struct RabinConfig {
+    min_size: usize,
+    expected_size: usize,
+    max_size: usize,
+    window_size: usize, // rabin fingerprint window size
+    alpha: u32,         // base of polynomial hash
+    seed: u32,          // value hash is compared with
+    mode: RabinMode,    // kind of hash
+    polynomial: u64,    // irreducible polynomial for fingerprint mode
+    buffer_size: usize, // initial size of buffer for reading from input
+}
+
+impl RabinConfig {
+    pub fn new(expected_size: usize) -> RabinConfig {
+        RabinConfig {
+            min_size: expected_size / 4,
+            expected_size,
+            max_size: expected_size.saturating_mul(4),
+            window_size: (expected_size / 4).saturating_sub(1),
+            alpha: 1_664_525,
+            seed: 0,
+            mode: RabinMode::Multiplicative,
+            polynomial: DEFAULT_POLYNOMIAL,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+        }
+    }
+
+    pub fn min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    pub fn window_size(mut self, window_size: usize) -> Self {
+        self.window_size = window_size;
+        self
+    }
+
+    pub fn alpha(mut self, alpha: u32) -> Self {
+        self.alpha = alpha;
+        self
+    }
+
+    pub fn seed(mut self, seed: u32) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    // restic chunks with fingerprint mode, window of 64 bytes, min 512 KiB, expected 1 MiB and max 8 MiB
+    pub fn mode(mut self, mode: RabinMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn polynomial(mut self, polynomial: u64) -> Self {
+        self.polynomial = polynomial;
+        self
+    }
+
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    pub fn build(self) -> Result<RabinChunker, ChunkerError> {
+        if self.expected_size == 0 {
+            return Err(ChunkerError::New("expected size must be positive"));
+        }
+        if self.expected_size > 1 << 31 {
+            return Err(ChunkerError::New("expected size is too big"));
+        }
+        if self.min_size > self.expected_size || self.expected_size > self.max_size {
+            return Err(ChunkerError::New("sizes must satisfy min <= expected <= max"));
+        }
+        if self.window_size == 0 {
+            return Err(ChunkerError::New("window size must be positive"));
+        }
+        if self.polynomial < 1 << 8 {
+            return Err(ChunkerError::New("polynomial degree must be at least 8"));
+        }
+        if !is_irreducible(self.polynomial) {
+            return Err(ChunkerError::New("polynomial must be irreducible"));
+        }
+        if self.buffer_size == 0 {
+            return Err(ChunkerError::New("buffer size must be positive"));
+        }
+
+        Ok(RabinChunker {
+            stream: ChunkStream::new(self.buffer_size),
+            window_size: self.window_size,
+            min_size: self.min_size,
+            max_size: self.max_size,
+            hash: HashRabin::new(self.expected_size, self.window_size, self.alpha, self.seed),
+            fingerprint: FingerprintRabin::new(self.polynomial, self.window_size),
+            mode: self.mode,
+            config: self,
+        })
+    }
+}
+
+pub struct RabinChunker {
+    stream: ChunkStream,           // for buffered reading from input
+    window_size: usize,            // rabin fingerprint window size
+    min_size: usize,               // min size of chunk
+    max_size: usize,               // max size of chunk
+    hash: HashRabin,               // struct for data of used Rabin hash parameters
+    fingerprint: FingerprintRabin, // tables for fingerprint mode
+    mode: RabinMode,               // kind of hash
+    config: RabinConfig,           // parameters chunker was built with
+}
+
+impl RabinChunker {
+    pub fn new(expected_size: usize, seed: u32) -> Result<RabinChunker, ChunkerError> {
+        RabinConfig::new(expected_size).seed(seed).build()
+    }
+
+    pub fn config(&self) -> &RabinConfig {
+        &self.config
+    }
+
+    // same chunks as sequential chunking, segments of input are shared between threads as slices
+    pub fn parallel_chunking(
+        &self,
+        path: &str,
+        threads_cnt: usize,
+    ) -> Result<(Duration, f64, f64), ChunkerError> {
+        parallel::parallel_chunking(self, path, threads_cnt)
+    }
+
+    fn find_cut_multiplicative(&self, data: &[u8]) -> (usize, CutReason) {
         let mut hash = 0u32;
-        let mut local_pos = 0;
-        let mut window = VecDeque::with_capacity(self.window_size);
-        loop {
-            // [0, self.buffered - 1] is already taken bytes
-            // max is end of data if it less than buffer size
-            let max = input
-                .read(&mut self.buffer[self.buffered..])
-                .map_err(ChunkerError::Read)?
-                + self.buffered;
-
-            // if max == 0 then end of input reached
-            if max == 0 {
-                return Ok(ChunkerStatus::Finished);
+        for i in 0..data.len().min(self.max_size) {
+            hash = self.hash.roll(hash, data, i, self.window_size);
+            if i >= self.window_size && i >= self.min_size && self.hash.matches(hash, self.hash.mask) {
+                return (i + 1, CutReason::Hash);
             }
+        }
+
+        // max chunk size reached
+        if data.len() >= self.max_size {
+            (self.max_size, CutReason::MaxSize)
+        } else {
+            (data.len(), CutReason::Eof)
+        }
+    }
+
+    // same bounds as restic: hashing starts window_size bytes before min_size
+    // with window as if it consisted of byte 1 and zeros
+    fn find_cut_fingerprint(&self, data: &[u8]) -> (usize, CutReason) {
+        let start = self.min_size.saturating_sub(self.window_size);
+        let mask = self.hash.mask as u64;
+        let mut hash = 1u64;
+        for (i, &cur_val) in data.iter().enumerate().skip(start) {
+            let front_window = match (i - start + 1).cmp(&self.window_size) {
+                Ordering::Less => 0,
+                Ordering::Equal => 1,
+                Ordering::Greater => data[i - self.window_size],
+            };
+            hash = self.fingerprint.slide(hash, front_window, cur_val);
 
-            for i in 0..max {
-                let cur_val = self.buffer[i];
-
-                // max chunk size reached
-                if local_pos >= self.max_size {
-                    // write remained data
-                    output
-                        .write_all(&self.buffer[..i + 1])
-                        .map_err(ChunkerError::Write)?;
-
-                    // move unprocessed data to the beginning of buffer
-                    unsafe {
-                        ptr::copy(
-                            self.buffer[i + 1..].as_ptr(),
-                            self.buffer.as_mut_ptr(),
-                            max - i - 1,
-                        )
-                    };
-                    self.buffered = max - i - 1;
-
-                    return Ok(ChunkerStatus::Working);
-                }
-
-                // Rabin fingerprint main algorithm
-                hash = hash
-                    .wrapping_mul(self.hash.alpha)
-                    .wrapping_add(cur_val as u32);
-                if local_pos >= self.window_size {
-                    let front_window = window.pop_front().unwrap();
-                    hash = hash.wrapping_sub(self.hash.pow_table[front_window as usize]);
-
-                    // next chunk finded
-                    if local_pos >= self.min_size && ((hash ^ self.hash.seed) & self.hash.mask) == 0
-                    {
-                        // write remained data
-                        output
-                            .write_all(&self.buffer[..i + 1])
-                            .map_err(ChunkerError::Write)?;
-
-                        // move unprocessed data to the beginning of buffer
-                        unsafe {
-                            ptr::copy(
-                                self.buffer[i + 1..].as_ptr(),
-                                self.buffer.as_mut_ptr(),
-                                max - i - 1,
-                            )
-                        };
-                        self.buffered = max - i - 1;
-
-                        return Ok(ChunkerStatus::Working);
-                    }
-                }
-                local_pos += 1;
-                window.push_back(cur_val);
+            if i + 1 >= self.min_size && ((hash ^ self.hash.seed as u64) & mask) == 0 {
+                return (i + 1, CutReason::Hash);
             }
+            if i + 1 >= self.max_size {
+                return (i + 1, CutReason::MaxSize);
+            }
+        }
 
-            // end of input or end of buffer reached
-            output
-                .write_all(&self.buffer[..max])
-                .map_err(ChunkerError::Write)?;
-            self.buffered = 0;
+        (data.len(), CutReason::Eof)
+    }
+}
+
+impl Chunker for RabinChunker {
+    fn find_cut(&self, data: &[u8]) -> (usize, CutReason) {
+        match self.mode {
+            RabinMode::Multiplicative => self.find_cut_multiplicative(data),
+            RabinMode::Fingerprint => self.find_cut_fingerprint(data),
         }
     }
+
+    fn stream(&mut self) -> &mut ChunkStream {
+        &mut self.stream
+    }
 }
@@ -0,0 +1,161 @@
+use super::chunker::*;
+use super::parallel;
+use std::time::Duration;
+
+// on random data chunk is window and distance to the first byte not less than max of window,
+// mean distance is 256 / (256 - max); default window is the largest one which mean chunk
+// is not greater than expected size (window_size + ~256 for big sizes)
+fn default_window_size(expected_size: usize) -> usize {
+    let mean_chunk = |window_size: usize| -> f64 {
+        let max_cdf = |m: usize| (m as f64 / 256.).powf(window_size as f64);
+        let distance: f64 = (0..256)
+            .map(|m| (max_cdf(m + 1) - max_cdf(m)) * 256. / (256 - m) as f64)
+            .sum();
+        window_size as f64 + distance
+    };
+
+    // mean chunk grows with window
+    let (mut low, mut high) = (1, expected_size.max(1));
+    while low < high {
+        let mid = low + (high - low).div_ceil(2);
+        if mean_chunk(mid) <= expected_size as f64 {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+
+    low
+}
+
+// parameters of RamChunker
+#[derive(Clone, Debug)]
+pub struct RamConfig {
+    min_size: usize,
+    expected_size: usize,
+    window_size: usize, // size of fixed window which max byte is threshold
+    max_size: usize,
+    buffer_size: usize, // initial size of buffer for reading from input
+}
+
+impl RamConfig {
+    pub fn new(expected_size: usize) -> RamConfig {
+        RamConfig {
+            // no min size in paper, window itself bounds chunk size from below
+            min_size: 0,
+            expected_size,
+            window_size: default_window_size(expected_size),
+            max_size: expected_size.saturating_mul(4),
+            buffer_size: DEFAULT_BUFFER_SIZE,
+        }
+    }
+
+    pub fn min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    pub fn window_size(mut self, window_size: usize) -> Self {
+        self.window_size = window_size;
+        self
+    }
+
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    pub fn build(self) -> Result<RamChunker, ChunkerError> {
+        if self.expected_size == 0 {
+            return Err(ChunkerError::New("expected size must be positive"));
+        }
+        if self.min_size > self.expected_size || self.expected_size > self.max_size {
+            return Err(ChunkerError::New(
+                "sizes must satisfy min <= expected <= max",
+            ));
+        }
+        if self.window_size == 0 {
+            return Err(ChunkerError::New("window size must be positive"));
+        }
+        // chunk is always longer than window
+        if self.window_size > self.expected_size {
+            return Err(ChunkerError::New(
+                "window size must not be greater than expected size",
+            ));
+        }
+        if self.window_size >= self.max_size {
+            return Err(ChunkerError::New("window size must be less than max size"));
+        }
+        if self.buffer_size == 0 {
+            return Err(ChunkerError::New("buffer size must be positive"));
+        }
+
+        Ok(RamChunker {
+            stream: ChunkStream::new(self.buffer_size),
+            window_size: self.window_size,
+            min_size: self.min_size,
+            max_size: self.max_size,
+            config: self,
+        })
+    }
+}
+
+// "RAM: Rapid Asymmetric Maximum content-defined chunking algorithm"
+pub struct RamChunker {
+    stream: ChunkStream, // for buffered reading from input
+    window_size: usize,  // size of fixed window which max byte is threshold
+    min_size: usize,     // no cut in first min_size bytes of chunk
+    max_size: usize,     // max size of chunk
+    config: RamConfig,   // parameters chunker was built with
+}
+
+impl RamChunker {
+    pub fn new(expected_size: usize) -> Result<RamChunker, ChunkerError> {
+        RamConfig::new(expected_size).build()
+    }
+
+    pub fn config(&self) -> &RamConfig {
+        &self.config
+    }
+
+    pub fn parallel_chunking(
+        &self,
+        path: &str,
+        threads_cnt: usize,
+    ) -> Result<(Duration, f64, f64), ChunkerError> {
+        parallel::parallel_chunking(self, path, threads_cnt)
+    }
+}
+
+impl Chunker for RamChunker {
+    fn find_cut(&self, data: &[u8]) -> (usize, CutReason) {
+        if data.len() <= self.window_size {
+            return (data.len(), CutReason::Eof);
+        }
+
+        // chunk is cut at the first byte after the window which is not less than its max
+        let threshold = data[..self.window_size].iter().copied().max().unwrap_or(0);
+        let start = self.window_size.max(self.min_size.saturating_sub(1));
+        let end = data.len().min(self.max_size);
+        for (i, &cur_val) in data[..end].iter().enumerate().skip(start) {
+            if cur_val >= threshold {
+                return (i + 1, CutReason::Hash);
+            }
+        }
+
+        if data.len() >= self.max_size {
+            (self.max_size, CutReason::MaxSize)
+        } else {
+            (data.len(), CutReason::Eof)
+        }
+    }
+
+    fn stream(&mut self) -> &mut ChunkStream {
+        &mut self.stream
+    }
+}
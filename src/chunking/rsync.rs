@@ -0,0 +1,162 @@
+use super::chunker::*;
+
+// weak rolling checksum of rsync (Adler-32 without modulo 65521): s1 is sum of window bytes,
+// s2 is sum of s1 over window prefixes, both modulo 2^16
+#[derive(Clone, Copy, Default, Debug)]
+pub struct RollingChecksum {
+    s1: u32,
+    s2: u32,
+    len: u32, // window size
+}
+
+impl RollingChecksum {
+    pub fn new(window: &[u8]) -> RollingChecksum {
+        let mut checksum = RollingChecksum::default();
+        window.iter().for_each(|&cur_val| checksum.push(cur_val));
+        checksum
+    }
+
+    // window grows by back byte
+    pub fn push(&mut self, back: u8) {
+        self.s1 = self.s1.wrapping_add(back as u32);
+        self.s2 = self.s2.wrapping_add(self.s1);
+        self.len = self.len.wrapping_add(1);
+    }
+
+    // window moves by one byte: front leaves it, back enters it
+    pub fn roll(&mut self, front: u8, back: u8) {
+        self.s1 = self.s1.wrapping_sub(front as u32).wrapping_add(back as u32);
+        self.s2 = self
+            .s2
+            .wrapping_sub(self.len.wrapping_mul(front as u32))
+            .wrapping_add(self.s1);
+    }
+
+    pub fn digest(&self) -> u32 {
+        (self.s1 & 0xffff) | (self.s2 << 16)
+    }
+}
+
+// rsync chunking: chunk is cut after the window which weak checksum matches mask,
+// the same checksum can be reused to search blocks of old file in new one, as rsync does
+
+// parameters of RsyncChunker
+#[derive(Clone, Debug)]
+pub struct RsyncConfig {
+    min_size: usize,
+    expected_size: usize,
+    max_size: usize,
+    window_size: usize, // checksum window size
+    buffer_size: usize, // initial size of buffer for reading from input
+}
+
+impl RsyncConfig {
+    pub fn new(expected_size: usize) -> RsyncConfig {
+        RsyncConfig {
+            min_size: expected_size / 4,
+            expected_size,
+            max_size: expected_size.saturating_mul(4),
+            window_size: (expected_size / 4).saturating_sub(1),
+            buffer_size: DEFAULT_BUFFER_SIZE,
+        }
+    }
+
+    pub fn min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    pub fn window_size(mut self, window_size: usize) -> Self {
+        self.window_size = window_size;
+        self
+    }
+
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    pub fn build(self) -> Result<RsyncChunker, ChunkerError> {
+        if self.expected_size == 0 {
+            return Err(ChunkerError::New("expected size must be positive"));
+        }
+        if self.expected_size > 1 << 31 {
+            return Err(ChunkerError::New("expected size is too big"));
+        }
+        if self.min_size > self.expected_size || self.expected_size > self.max_size {
+            return Err(ChunkerError::New(
+                "sizes must satisfy min <= expected <= max",
+            ));
+        }
+        if self.window_size == 0 {
+            return Err(ChunkerError::New("window size must be positive"));
+        }
+        if self.buffer_size == 0 {
+            return Err(ChunkerError::New("buffer size must be positive"));
+        }
+
+        Ok(RsyncChunker {
+            stream: ChunkStream::new(self.buffer_size),
+            window_size: self.window_size,
+            min_size: self.min_size,
+            max_size: self.max_size,
+            mask: (self.expected_size as u32).next_power_of_two() - 1,
+            config: self,
+        })
+    }
+}
+
+pub struct RsyncChunker {
+    stream: ChunkStream, // for buffered reading from input
+    window_size: usize,  // checksum window size
+    min_size: usize,     // min size of chunk
+    max_size: usize,     // max size of chunk
+    mask: u32,           // chunk is cut when checksum & mask == 0
+    config: RsyncConfig, // parameters chunker was built with
+}
+
+impl RsyncChunker {
+    pub fn new(expected_size: usize) -> Result<RsyncChunker, ChunkerError> {
+        RsyncConfig::new(expected_size).build()
+    }
+
+    pub fn config(&self) -> &RsyncConfig {
+        &self.config
+    }
+}
+
+impl Chunker for RsyncChunker {
+    fn find_cut(&self, data: &[u8]) -> (usize, CutReason) {
+        let mut checksum = RollingChecksum::default();
+        for (i, &cur_val) in data.iter().enumerate().take(self.max_size) {
+            if i < self.window_size {
+                checksum.push(cur_val);
+            } else {
+                checksum.roll(data[i - self.window_size], cur_val);
+            }
+
+            // s2 changes more between neighbour windows than s1, so its bits are checked first
+            if i + 1 >= self.window_size
+                && i + 1 >= self.min_size
+                && checksum.digest().rotate_left(16) & self.mask == 0
+            {
+                return (i + 1, CutReason::Hash);
+            }
+        }
+
+        if data.len() >= self.max_size {
+            (self.max_size, CutReason::MaxSize)
+        } else {
+            (data.len(), CutReason::Eof)
+        }
+    }
+
+    fn stream(&mut self) -> &mut ChunkStream {
+        &mut self.stream
+    }
+}
@@ -0,0 +1,371 @@
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+
+use super::chunker::*;
+use super::parallel;
+use std::time::{Duration, Instant};
+
+// tar-aware chunking: headers of every member of ustar/pax archive are one chunk, data of member
+// (with padding to block) is chunked by inner chunker, so chunks never cross member boundaries
+// and change of one member doesn't move chunks of others;
+// cuts depend on position in archive, which is the state carried between cuts
+// by Boundaries and next_chunk
+
+const BLOCK_SIZE: usize = 512;
+
+// size of data rounded up to whole blocks
+fn padded(size: u64) -> u64 {
+    size.div_ceil(BLOCK_SIZE as u64) * BLOCK_SIZE as u64
+}
+
+// numeric field of header: octal digits terminated by space or NUL, or base-256 (GNU) if high bit is set
+fn parse_number(field: &[u8]) -> Option<u64> {
+    if field[0] & 0x80 != 0 {
+        return field[1..]
+            .iter()
+            .try_fold(u64::from(field[0] & 0x7f), |value, &byte| {
+                value.checked_mul(256).map(|value| value | byte as u64)
+            });
+    }
+
+    let digits = field
+        .iter()
+        .skip_while(|&&byte| byte == b' ' || byte == 0)
+        .take_while(|&&byte| byte != b' ' && byte != 0);
+    let mut value = 0u64;
+    for &digit in digits {
+        if !(b'0'..=b'7').contains(&digit) {
+            return None;
+        }
+        value = value.checked_mul(8)? | (digit - b'0') as u64;
+    }
+
+    Some(value)
+}
+
+// block is a header if its checksum is right, checksum field itself is counted as spaces
+fn is_header(block: &[u8]) -> bool {
+    let sum: u64 = block
+        .iter()
+        .enumerate()
+        .map(|(i, &byte)| if (148..156).contains(&i) { b' ' } else { byte } as u64)
+        .sum();
+
+    // zero block ends archive
+    block.iter().any(|&byte| byte != 0) && parse_number(&block[148..156]) == Some(sum)
+}
+
+// value of "size" record of pax extended header, records are "<length> <key>=<value>\n"
+fn pax_size(mut records: &[u8]) -> Option<u64> {
+    let mut size = None;
+    while !records.is_empty() {
+        let space = records.iter().position(|&byte| byte == b' ')?;
+        let length: usize = std::str::from_utf8(&records[..space]).ok()?.parse().ok()?;
+        if length <= space || length > records.len() {
+            return None;
+        }
+
+        let record = &records[space + 1..length];
+        if let Some(value) = record.strip_prefix(b"size=") {
+            let value = value.strip_suffix(b"\n").unwrap_or(value);
+            size = std::str::from_utf8(value).ok()?.parse().ok();
+        }
+        records = &records[length..];
+    }
+
+    size
+}
+
+// first member at the beginning of data
+enum Member {
+    Valid(usize, u64), // size of all its headers, size of its data with padding
+    Truncated,         // headers don't fit in data
+    Invalid,           // end of archive or damaged header
+}
+
+fn parse_member(data: &[u8]) -> Member {
+    let mut pos = 0;
+    let mut size_override = None;
+    loop {
+        let Some(header) = data.get(pos..pos + BLOCK_SIZE) else {
+            return Member::Truncated;
+        };
+        if !is_header(header) {
+            return Member::Invalid;
+        }
+        let Some(size) = parse_number(&header[124..136]) else {
+            return Member::Invalid;
+        };
+        pos += BLOCK_SIZE;
+
+        match header[156] {
+            // pax extended headers and GNU long names describe the next header
+            b'x' | b'g' | b'L' | b'K' => {
+                let Some(extension) = usize::try_from(size)
+                    .ok()
+                    .and_then(|size| pos.checked_add(size))
+                    .and_then(|end| data.get(pos..end))
+                else {
+                    return Member::Truncated;
+                };
+                if header[156] == b'x' {
+                    size_override = pax_size(extension).or(size_override);
+                }
+                pos += padded(size) as usize;
+            }
+            // special files have no data, size field of them is ignored
+            b'2'..=b'6' => return Member::Valid(pos, 0),
+            _ => return Member::Valid(pos, padded(size_override.unwrap_or(size))),
+        }
+    }
+}
+
+// position of chunk in archive, state of TarChunker
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Position {
+    Headers,   // at headers of member
+    Data(u64), // in data of member, bytes left of it (never 0)
+    Rest,      // end of archive or data after damaged header, chunked by inner chunker
+}
+
+impl From<u64> for Position {
+    fn from(state: u64) -> Self {
+        match state {
+            0 => Position::Headers,
+            u64::MAX => Position::Rest,
+            left => Position::Data(left),
+        }
+    }
+}
+
+impl From<Position> for u64 {
+    fn from(position: Position) -> Self {
+        match position {
+            Position::Headers => 0,
+            Position::Data(left) => left,
+            Position::Rest => u64::MAX,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RegionKind {
+    Headers, // headers of member are one chunk
+    Data,    // data of member is chunked by inner chunker, its end is a cut
+    Rest,    // rest of archive is chunked by inner chunker
+    Tail,    // truncated headers are the last chunk
+}
+
+// part of archive chunked as a whole
+#[derive(Clone, Copy, Debug)]
+struct Region {
+    start: usize,
+    end: usize,
+    kind: RegionKind,
+}
+
+// regions of archive in order, same as positions next_chunk goes through
+fn regions(data: &[u8]) -> Vec<Region> {
+    let mut result = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let (end, kind) = match parse_member(&data[pos..]) {
+            Member::Valid(headers_size, data_size) => {
+                result.push(Region {
+                    start: pos,
+                    end: pos + headers_size,
+                    kind: RegionKind::Headers,
+                });
+                pos += headers_size;
+                match usize::try_from(data_size)
+                    .ok()
+                    .and_then(|size| pos.checked_add(size))
+                {
+                    Some(end) if end <= data.len() => (end, RegionKind::Data),
+                    // truncated data of member is chunked up to the end of input
+                    _ => (data.len(), RegionKind::Rest),
+                }
+            }
+            Member::Truncated => (data.len(), RegionKind::Tail),
+            Member::Invalid => (data.len(), RegionKind::Rest),
+        };
+
+        if pos < end {
+            result.push(Region {
+                start: pos,
+                end,
+                kind,
+            });
+        }
+        pos = end;
+    }
+
+    result
+}
+
+pub struct TarChunker<C> {
+    stream: ChunkStream, // for buffered reading from input
+    inner: C,            // chunker for data of members
+}
+
+impl<C: Chunker> TarChunker<C> {
+    pub fn new(inner: C) -> TarChunker<C> {
+        TarChunker {
+            stream: ChunkStream::default(),
+            inner,
+        }
+    }
+
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.stream = ChunkStream::new(buffer_size);
+        self
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+
+    // same chunks as boundaries gives: regions of archive are independent, so they are split
+    // between threads by size, big data of member is chunked in parallel by inner chunker
+    // (generic parallel_boundaries meets sequential bounds only after the end of archive)
+    pub fn parallel_boundaries(&self, data: &[u8], threads_cnt: usize) -> Vec<ChunkInfo>
+    where
+        C: Sync,
+    {
+        let threads_cnt = threads_cnt.max(1);
+        let share = data.len().div_ceil(threads_cnt).max(1);
+        let mut tasks = vec![Vec::new(); threads_cnt];
+        for region in regions(data) {
+            tasks[region.start / share].push(region);
+        }
+
+        let inner = &self.inner;
+        let chunks: Vec<Vec<ChunkInfo>> = tasks
+            .into_par_iter()
+            .map(|task| {
+                task.into_iter()
+                    .flat_map(|region| region_chunks(inner, data, region, share))
+                    .collect()
+            })
+            .collect();
+
+        chunks.concat()
+    }
+
+    // (time, part of unique data, average size of unique chunk) for parallel chunking of archive
+    pub fn parallel_chunking(
+        &self,
+        path: &str,
+        threads_cnt: usize,
+    ) -> Result<(Duration, f64, f64), ChunkerError>
+    where
+        C: Sync,
+    {
+        let vec = self.inner.read_file(path).map_err(ChunkerError::Read)?;
+
+        let now = Instant::now();
+        let chunks = self.parallel_boundaries(&vec, threads_cnt);
+        let (coeff, avg) = parallel::dedup_stats(&vec, &chunks);
+
+        Ok((now.elapsed(), coeff, avg))
+    }
+
+    // cut at the beginning of data at given position and position of the chunk after it
+    fn cut(&self, data: &[u8], position: Position) -> (usize, CutReason, Position) {
+        match position {
+            Position::Headers => match parse_member(data) {
+                Member::Valid(headers_size, 0) => {
+                    (headers_size, CutReason::Member, Position::Headers)
+                }
+                Member::Valid(headers_size, data_size) => {
+                    (headers_size, CutReason::Member, Position::Data(data_size))
+                }
+                Member::Truncated => (data.len(), CutReason::Eof, Position::Headers),
+                Member::Invalid => {
+                    let (length, reason) = self.inner.find_cut(data);
+                    (length, reason, Position::Rest)
+                }
+            },
+            Position::Data(left) if (data.len() as u64) < left => {
+                let (length, reason) = self.inner.find_cut(data);
+                (length, reason, Position::Data(left - length as u64))
+            }
+            Position::Data(left) => {
+                // end of member is visible, so it is the end of input for inner chunker
+                let (length, reason) = self.inner.find_cut(&data[..left as usize]);
+                let reason = match reason {
+                    CutReason::Eof => CutReason::Member,
+                    reason => reason,
+                };
+                let position = match left - length as u64 {
+                    0 => Position::Headers,
+                    left => Position::Data(left),
+                };
+                (length, reason, position)
+            }
+            Position::Rest => {
+                let (length, reason) = self.inner.find_cut(data);
+                (length, reason, Position::Rest)
+            }
+        }
+    }
+}
+
+// chunks of region of archive, it is split between ceil(size / share) threads
+fn region_chunks<C: Chunker + Sync>(
+    inner: &C,
+    data: &[u8],
+    region: Region,
+    share: usize,
+) -> Vec<ChunkInfo> {
+    let length = region.end - region.start;
+    let whole = |reason| {
+        vec![ChunkInfo {
+            offset: region.start as u64,
+            length,
+            reason,
+            hash: None,
+        }]
+    };
+
+    match region.kind {
+        RegionKind::Headers => whole(CutReason::Member),
+        RegionKind::Tail => whole(CutReason::Eof),
+        RegionKind::Data | RegionKind::Rest => {
+            let threads_cnt = length.div_ceil(share);
+            let mut chunks = if threads_cnt > 1 {
+                parallel::parallel_boundaries(inner, &data[region.start..region.end], threads_cnt)
+            } else {
+                Boundaries::new(inner, &data[region.start..region.end]).collect()
+            };
+            for info in &mut chunks {
+                info.offset += region.start as u64;
+                if region.kind == RegionKind::Data && info.reason == CutReason::Eof {
+                    info.reason = CutReason::Member;
+                }
+            }
+            chunks
+        }
+    }
+}
+
+impl<C: Chunker> Chunker for TarChunker<C> {
+    // data is taken as the beginning of archive
+    fn find_cut(&self, data: &[u8]) -> (usize, CutReason) {
+        let (length, reason, _) = self.cut(data, Position::Headers);
+        (length, reason)
+    }
+
+    // state is position of chunk in archive
+    fn find_cut_with_state(&self, data: &[u8], state: u64) -> (usize, CutReason, u64) {
+        let (length, reason, position) = self.cut(data, Position::from(state));
+        (length, reason, position.into())
+    }
+
+    fn stream(&mut self) -> &mut ChunkStream {
+        &mut self.stream
+    }
+}
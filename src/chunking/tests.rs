@@ -1,7 +1,102 @@
+use super::chunker::*;
+#[cfg(feature = "buzhash")]
+use super::hash::generate_seq;
+#[allow(unused_imports)] // only tests of some features use it
+use super::hash::random_data;
+use super::iter::*;
+use super::parallel::*;
+#[cfg(feature = "pci")]
+use super::pci::*;
+#[cfg(feature = "ae")]
+use super::ae::*;
+#[cfg(feature = "buzhash")]
+use super::buzhash::*;
+#[cfg(feature = "fastcdc")]
+use super::fastcdc::*;
+#[cfg(feature = "fixed")]
+use super::fixed::*;
+#[cfg(feature = "gear")]
+use super::gear::*;
+#[cfg(feature = "leap")]
+use super::leap::*;
+#[cfg(feature = "maxp")]
+use super::maxp::*;
+#[cfg(feature = "rabin")]
+use super::rabin::*;
+#[cfg(feature = "ram")]
+use super::ram::*;
+#[cfg(feature = "rsync")]
+use super::rsync::*;
+#[cfg(all(feature = "tar", feature = "gear"))]
+use super::tar::*;
+#[cfg(feature = "tttd")]
+use super::tttd::*;
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// deterministic pseudo-random data with low-entropy (zero and repeated) parts
+fn generate_data(len: usize, seed: u64) -> Vec<u8> {
+    let mut cur_value = seed;
+    (0..len)
+        .map(|i| {
+            cur_value = cur_value
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            match i % 100_000 {
+                0..=9_999 => 0,
+                10_000..=14_999 => (i % 7) as u8,
+                _ => (cur_value >> 56) as u8,
+            }
+        })
+        .collect()
+}
+
+// reader returning at most `step(n)` bytes on n-th call
+struct SplitReader<'a, F: FnMut(usize) -> usize> {
+    data: &'a [u8],
+    calls: usize,
+    step: F,
+}
+
+impl<'a, F: FnMut(usize) -> usize> SplitReader<'a, F> {
+    fn new(data: &'a [u8], step: F) -> Self {
+        SplitReader {
+            data,
+            calls: 0,
+            step,
+        }
+    }
+}
+
+impl<F: FnMut(usize) -> usize> Read for SplitReader<'_, F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.calls += 1;
+        let len = (self.step)(self.calls).min(buf.len()).min(self.data.len());
+        buf[..len].copy_from_slice(&self.data[..len]);
+        self.data = &self.data[len..];
+        Ok(len)
+    }
+}
+
+fn split_reader<'a>(kind: &str, data: &'a [u8]) -> Box<dyn Read + 'a> {
+    match kind {
+        "1-byte" => Box::new(SplitReader::new(data, |_| 1)),
+        "odd" => Box::new(SplitReader::new(data, |n| 2 * n + 1)),
+        "random" => {
+            let mut seed = 1u64;
+            Box::new(SplitReader::new(data, move |_| {
+                seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+                (seed >> 52) as usize + 1
+            }))
+        }
+        _ => Box::new(data),
+    }
+}
+
 fn test_chunking(chunker: &mut dyn Chunker, data: &mut dyn Read) -> Vec<Vec<u8>> {
     let mut chunks: Vec<Vec<u8>> = vec![];
     let mut chunk: Vec<u8> = vec![];
-    while chunker.next_chunk(data, &mut chunk).unwrap() == ChunkerStatus::Working {
+    while chunker.next_chunk(data, &mut chunk).unwrap().status() == ChunkerStatus::Working {
         chunks.push(chunk);
         chunk = vec![];
     }
@@ -10,45 +105,947 @@ fn test_chunking(chunker: &mut dyn Chunker, data: &mut dyn Read) -> Vec<Vec<u8>>
     chunks
 }
 
-fn internal_chunking(path: &str, n: usize) -> Vec<Chunk> {
-    let contents = std::fs::read(path).unwrap();
-    let chunker = fastcdc::v2020::FastCDC::new(
-        &contents,
-        (n as u32) * 256,
-        (n as u32) * 1024,
-        (n as u32) * 8 * 1024,
+fn stream_chunks(mut chunker: Box<dyn Chunker>, data: &mut dyn Read) -> Vec<ChunkInfo> {
+    ChunkIter::new(&mut chunker, data)
+        .map(|chunk| chunk.unwrap().info)
+        .collect()
+}
+
+// every chunker under test, built with given initial buffer size
+// (there is none with only tar feature, TarChunker is tested on its own)
+#[cfg_attr(
+    not(any(
+        feature = "ae", feature = "buzhash", feature = "fastcdc", feature = "fixed",
+        feature = "gear", feature = "leap", feature = "maxp", feature = "pci",
+        feature = "rabin", feature = "ram", feature = "rsync", feature = "tttd"
+    )),
+    allow(unused_variables)
+)]
+fn chunkers(buffer_size: usize) -> Vec<(&'static str, Box<dyn Chunker + Sync>)> {
+    vec![
+        #[cfg(feature = "ae")]
+        (
+            "ae",
+            Box::new(AeConfig::new(4096).buffer_size(buffer_size).build().unwrap()),
+        ),
+        #[cfg(feature = "ae")]
+        (
+            "ae-min-u64",
+            Box::new(
+                AeConfig::new(4096)
+                    .min_size(1024)
+                    .max_size(8192)
+                    .extremum(AeExtremum::Min)
+                    .value(AeValue::U64)
+                    .buffer_size(buffer_size)
+                    .build()
+                    .unwrap(),
+            ),
+        ),
+        #[cfg(feature = "buzhash")]
+        (
+            "buzhash",
+            Box::new(BuzHashConfig::new(4096).seed(1).buffer_size(buffer_size).build().unwrap()),
+        ),
+        #[cfg(feature = "fastcdc")]
+        (
+            "fastcdc",
+            Box::new(FastCdcConfig::new(4096).seed(1).buffer_size(buffer_size).build().unwrap()),
+        ),
+        #[cfg(feature = "fixed")]
+        (
+            "fixed",
+            Box::new(FixedChunker::new(3000).unwrap().buffer_size(buffer_size)),
+        ),
+        #[cfg(feature = "gear")]
+        (
+            "gear",
+            Box::new(GearConfig::new(4096).seed(1).buffer_size(buffer_size).build().unwrap()),
+        ),
+        #[cfg(feature = "leap")]
+        (
+            "leap",
+            Box::new(LeapCdcConfig::new(4096).seed(1).buffer_size(buffer_size).build().unwrap()),
+        ),
+        #[cfg(feature = "maxp")]
+        (
+            "maxp",
+            Box::new(MaxpConfig::new(4096).buffer_size(buffer_size).build().unwrap()),
+        ),
+        #[cfg(feature = "pci")]
+        (
+            "pci",
+            Box::new(PciConfig::new(4096).buffer_size(buffer_size).build().unwrap()),
+        ),
+        #[cfg(feature = "rabin")]
+        (
+            "rabin",
+            Box::new(RabinConfig::new(4096).buffer_size(buffer_size).build().unwrap()),
+        ),
+        #[cfg(feature = "rabin")]
+        (
+            "rabin-fingerprint",
+            Box::new(
+                RabinConfig::new(4096)
+                    .window_size(64)
+                    .mode(RabinMode::Fingerprint)
+                    .buffer_size(buffer_size)
+                    .build()
+                    .unwrap(),
+            ),
+        ),
+        #[cfg(feature = "ram")]
+        (
+            "ram",
+            Box::new(RamConfig::new(4096).buffer_size(buffer_size).build().unwrap()),
+        ),
+        #[cfg(feature = "rsync")]
+        (
+            "rsync",
+            Box::new(RsyncConfig::new(4096).buffer_size(buffer_size).build().unwrap()),
+        ),
+        #[cfg(all(feature = "tar", feature = "gear"))]
+        (
+            "tar-gear",
+            Box::new(
+                TarChunker::new(GearConfig::new(4096).seed(1).build().unwrap())
+                    .buffer_size(buffer_size),
+            ),
+        ),
+        #[cfg(feature = "tttd")]
+        (
+            "tttd",
+            Box::new(TttdConfig::new(4096).max_size(8192).buffer_size(buffer_size).build().unwrap()),
+        ),
+    ]
+}
+
+#[test]
+fn read_split_invariance() {
+    let data = generate_data(300_000, 7);
+
+    for buffer_size in [1, 100, DEFAULT_BUFFER_SIZE, 1 << 20] {
+        let expected: Vec<Vec<ChunkInfo>> = chunkers(buffer_size)
+            .into_iter()
+            .map(|(_, chunker)| stream_chunks(chunker, &mut &data[..]))
+            .collect();
+
+        for reader_name in ["1-byte", "odd", "random"] {
+            for ((name, chunker), expected) in chunkers(buffer_size).into_iter().zip(&expected) {
+                assert_eq!(
+                    &stream_chunks(chunker, &mut split_reader(reader_name, &data)),
+                    expected,
+                    "{name} with {reader_name} reads and buffer size {buffer_size}"
+                );
+            }
+        }
+    }
+}
+
+// chunkers with expected sizes from both ends of supported range: 256 B and MiBs
+#[cfg(all(feature = "ae", feature = "fastcdc"))]
+#[test]
+fn read_split_invariance_at_size_limits() {
+    // (input length, name, chunker), chunker is built anew for every reader
+    type Build = fn() -> Box<dyn Chunker>;
+    let cases: [(usize, &str, Build); 4] = [
+        (300_000, "ae-256", || Box::new(AeConfig::new(256).build().unwrap())),
+        (300_000, "fastcdc-256", || Box::new(FastCdcConfig::new(256).build().unwrap())),
+        (24 << 20, "ae-1m", || Box::new(AeConfig::new(1 << 20).build().unwrap())),
+        (40 << 20, "fastcdc-4m", || Box::new(FastCdcConfig::new(4 << 20).build().unwrap())),
+    ];
+
+    for (len, name, build) in cases {
+        let data = generate_data(len, 37);
+        let expected: Vec<ChunkInfo> = build().boundaries(&data).collect();
+        assert!(expected.len() > 4, "{name}");
+        // 1-byte reads of MiBs are too slow for debug builds
+        let readers: &[&str] = if len < 1 << 20 { &["1-byte", "odd", "random"] } else { &["odd", "random"] };
+        for reader_name in readers {
+            assert_eq!(
+                stream_chunks(build(), &mut split_reader(reader_name, &data)),
+                expected,
+                "{name} with {reader_name} reads"
+            );
+        }
+    }
+}
+
+#[test]
+fn stream_matches_boundaries() {
+    let data = generate_data(300_000, 11);
+
+    for (name, chunker) in chunkers(DEFAULT_BUFFER_SIZE) {
+        let bounds: Vec<ChunkInfo> = Boundaries::new(chunker.as_ref(), &data).collect();
+        assert_eq!(stream_chunks(chunker, &mut &data[..]), bounds, "{name}");
+
+        // chunks cover the whole input
+        let mut offset = 0;
+        for info in &bounds {
+            assert_eq!(info.offset, offset, "{name}");
+            assert!(info.length > 0, "{name}");
+            offset += info.length as u64;
+        }
+        assert_eq!(offset, data.len() as u64, "{name}");
+    }
+}
+
+#[test]
+fn strong_hash_of_every_chunk() {
+    let data = generate_data(200_000, 31);
+
+    for (name, mut chunker) in chunkers(DEFAULT_BUFFER_SIZE) {
+        let bounds: Vec<ChunkInfo> = Boundaries::new(chunker.as_ref(), &data).with_strong_hash().collect();
+        assert!(bounds.len() > 1, "{name}");
+        for info in &bounds {
+            assert_eq!(info.hash, Some(strong_hash(&data[info.range()])), "{name}");
+        }
+
+        // hash is not computed unless requested
+        assert!(Boundaries::new(chunker.as_ref(), &data).all(|info| info.hash.is_none()), "{name}");
+
+        chunker.set_strong_hash(true);
+        let streamed: Vec<ChunkInfo> = ChunkIter::new(&mut chunker, &mut split_reader("odd", &data))
+            .map(|chunk| chunk.unwrap().info)
+            .collect();
+        assert_eq!(streamed, bounds, "{name}");
+    }
+}
+
+#[test]
+fn chunks_concatenate_to_input() {
+    let data = generate_data(100_000, 3);
+
+    for (name, mut chunker) in chunkers(DEFAULT_BUFFER_SIZE) {
+        let chunks = test_chunking(&mut chunker, &mut SplitReader::new(&data, |n| n % 97 + 1));
+        assert_eq!(chunks.concat(), data, "{name}");
+    }
+}
+
+#[test]
+fn parallel_matches_sequential() {
+    let mut inputs = vec![
+        generate_data(300_000, 5),
+        vec![0; 100_000],
+        (0..100_000u32).map(|i| (i / 300) as u8).collect(),
+        generate_data(1000, 6),
+        vec![],
+    ];
+    inputs.push(inputs[0].iter().rev().copied().collect());
+
+    for data in &inputs {
+        for (name, chunker) in chunkers(DEFAULT_BUFFER_SIZE) {
+            let sequential: Vec<ChunkInfo> = Boundaries::new(chunker.as_ref(), data).collect();
+            for threads_cnt in 1..=33 {
+                assert_eq!(
+                    parallel_boundaries(chunker.as_ref(), data, threads_cnt),
+                    sequential,
+                    "{name} with {threads_cnt} threads on {} bytes",
+                    data.len()
+                );
+            }
+        }
+    }
+}
+
+// chunker counting find_cut calls
+struct CountingChunker {
+    inner: Box<dyn Chunker + Sync>,
+    calls: AtomicUsize,
+}
+
+impl Chunker for CountingChunker {
+    fn find_cut(&self, data: &[u8]) -> (usize, CutReason) {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        self.inner.find_cut(data)
+    }
+
+    fn stream(&mut self) -> &mut ChunkStream {
+        self.inner.stream()
+    }
+}
+
+// bounds of segments never meet for fixed chunks and runs of max-size cuts on constant data,
+// still every chunk is cut at most twice: in its segment and by joining of segments
+#[test]
+fn parallel_work_is_bounded_by_input() {
+    let data = vec![0; 1 << 20];
+
+    for (name, inner) in chunkers(DEFAULT_BUFFER_SIZE) {
+        let chunker = CountingChunker {
+            inner,
+            calls: AtomicUsize::new(0),
+        };
+        let chunks_cnt = Boundaries::new(&chunker, &data).count();
+        for threads_cnt in [2, 8, 32] {
+            chunker.calls.store(0, Ordering::Relaxed);
+            parallel_boundaries(&chunker, &data, threads_cnt);
+            let calls = chunker.calls.load(Ordering::Relaxed);
+            assert!(
+                calls <= 2 * chunks_cnt + threads_cnt,
+                "{name} with {threads_cnt} threads: {calls} cuts for {chunks_cnt} chunks"
+            );
+        }
+    }
+}
+
+#[cfg(feature = "rabin")]
+#[test]
+fn rabin_parallel_chunking_matches_sequential() {
+    let data = generate_data(400_000, 9);
+    let path = std::env::temp_dir().join(format!("cdc-rabin-{}.bin", std::process::id()));
+    std::fs::write(&path, &data).unwrap();
+
+    let chunker = RabinChunker::new(4096, 0).unwrap();
+    let sequential: Vec<ChunkInfo> = chunker.boundaries(&data).collect();
+    let expected = dedup_stats(&data, &sequential);
+    for threads_cnt in 1..=8 {
+        let (_, coeff, avg) = chunker.parallel_chunking(path.to_str().unwrap(), threads_cnt).unwrap();
+        assert_eq!((coeff, avg), expected, "{threads_cnt} threads");
+    }
+
+    std::fs::remove_file(&path).unwrap();
+
+    // missing file is an error, not a panic
+    assert!(matches!(
+        chunker.parallel_chunking(path.to_str().unwrap(), 2),
+        Err(ChunkerError::Read(_))
+    ));
+}
+
+#[cfg(feature = "ram")]
+#[test]
+fn ram_cuts_at_first_byte_not_less_than_window_max() {
+    let data = generate_data(300_000, 19);
+    let chunker = RamConfig::new(1024).window_size(700).build().unwrap();
+
+    for info in chunker.boundaries(&data) {
+        let chunk = &data[info.range()];
+        if chunk.len() <= 700 {
+            assert_eq!(info.reason, CutReason::Eof);
+            continue;
+        }
+
+        let threshold = *chunk[..700].iter().max().unwrap();
+        let (last, middle) = chunk[700..].split_last().unwrap();
+        assert!(middle.iter().all(|&cur_val| cur_val < threshold));
+        assert_eq!(*last >= threshold, info.reason == CutReason::Hash, "at {}", info.offset);
+    }
+
+    // no cut before min size, bytes between window and min size don't end chunk
+    let chunker = RamConfig::new(1024).window_size(300).min_size(900).build().unwrap();
+    for info in chunker.boundaries(&data) {
+        assert!(info.length >= 900 || info.reason == CutReason::Eof, "at {}", info.offset);
+    }
+
+    // default window keeps mean chunk close to expected size for small sizes too
+    let random = random_data(2_000_000, 3);
+    for expected_size in [64, 256, 1024, 8192] {
+        let chunker = RamChunker::new(expected_size).unwrap();
+        let (mean, _) = size_stats(&chunker.boundaries(&random).collect::<Vec<_>>());
+        let ratio = mean / expected_size as f64;
+        assert!((0.7..1.3).contains(&ratio), "{expected_size}: {ratio}");
+    }
+
+    assert!(is_new_error(RamConfig::new(0).build()));
+    assert!(is_new_error(RamConfig::new(1024).min_size(1025).build()));
+    assert!(is_new_error(RamConfig::new(1024).max_size(1023).build()));
+    assert!(is_new_error(RamConfig::new(1024).window_size(0).build()));
+    assert!(is_new_error(RamConfig::new(1024).window_size(1025).build()));
+}
+
+#[cfg(feature = "ae")]
+#[test]
+fn ae_guards_increasing_input() {
+    let data: Vec<u8> = (0..100_000u32).map(|i| (i / 256) as u8).collect();
+    let chunker = AeConfig::new(1024).min_size(256).max_size(4096).build().unwrap();
+
+    let bounds: Vec<ChunkInfo> = chunker.boundaries(&data).collect();
+    assert!(bounds.iter().all(|info| info.length <= 4096));
+    assert!(bounds[..bounds.len() - 1].iter().all(|info| info.length >= 256));
+    assert!(bounds.iter().any(|info| info.reason == CutReason::MaxSize));
+}
+
+#[cfg(feature = "ae")]
+#[test]
+fn ae_variants_avoid_max_size_cuts_on_sparse_data() {
+    // mostly zeros with rare bytes slowly growing through the file: max byte is renewed
+    // more often than window passes, so AE-Max on single bytes ends chunks by max size
+    let data: Vec<u8> = random_data(1_000_000, 1)
+        .into_iter()
+        .enumerate()
+        .map(|(i, value)| if value.is_multiple_of(64) { (i / 3000) as u8 } else { 0 })
+        .collect();
+    let max_size_cuts = |extremum: AeExtremum, value: AeValue| {
+        let chunker = AeConfig::new(4096).extremum(extremum).value(value).build().unwrap();
+        chunker.boundaries(&data).filter(|info| info.reason == CutReason::MaxSize).count()
+    };
+
+    let max_u8 = max_size_cuts(AeExtremum::Max, AeValue::U8);
+    assert!(max_u8 > 40, "{max_u8}");
+    for value in [AeValue::U32, AeValue::U64] {
+        let cuts = max_size_cuts(AeExtremum::Max, value);
+        assert!(cuts * 2 < max_u8, "{value:?}: {cuts} of {max_u8}");
+    }
+    for value in [AeValue::U8, AeValue::U32, AeValue::U64] {
+        let cuts = max_size_cuts(AeExtremum::Min, value);
+        assert!(cuts * 10 < max_u8, "min {value:?}: {cuts} of {max_u8}");
+    }
+}
+
+#[cfg(feature = "maxp")]
+#[test]
+fn maxp_cuts_at_first_strict_local_maximum() {
+    let data = generate_data(60_000, 23);
+    let (min_size, max_size, window_size) = (128, 2048, 200);
+    let chunker = MaxpConfig::new(512)
+        .min_size(min_size)
+        .max_size(max_size)
+        .window_size(window_size)
+        .build()
+        .unwrap();
+    let value = |chunk: &[u8], pos: usize| u64::from_be_bytes(chunk[pos - 7..=pos].try_into().unwrap());
+
+    for info in chunker.boundaries(&data) {
+        let chunk = &data[info.range()];
+        // values after max size of chunk are not compared
+        let view = &data[info.offset as usize..(info.offset as usize + max_size).min(data.len())];
+        for pos in (window_size + 7).max(min_size - 1)..chunk.len() {
+            let right_end = (pos + window_size + 1).min(view.len());
+            let cur_val = value(view, pos);
+            let is_max = (pos - window_size..pos)
+                .chain(pos + 1..right_end)
+                .all(|i| value(view, i) < cur_val);
+            let is_confirmed = pos + window_size < view.len() || view.len() == max_size;
+            assert_eq!(
+                is_max && is_confirmed,
+                pos + 1 == chunk.len() && info.reason == CutReason::Hash,
+                "at {}",
+                info.offset as usize + pos
+            );
+        }
+    }
+
+    assert!(is_new_error(MaxpConfig::new(4096).window_size(usize::MAX).build()));
+    assert!(is_new_error(MaxpConfig::new(4096).window_size(usize::MAX - 16_384).build()));
+}
+
+#[cfg(all(feature = "fastcdc", feature = "rabin"))]
+#[test]
+fn max_size_cuts_are_exactly_max_size() {
+    let data = generate_data(1_000_000, 23);
+    let chunkers: Vec<Box<dyn Chunker>> = vec![
+        Box::new(FastCdcConfig::new(4096).max_size(4096).build().unwrap()),
+        Box::new(RabinConfig::new(4096).max_size(4096).build().unwrap()),
+    ];
+
+    for chunker in chunkers {
+        let bounds: Vec<ChunkInfo> = chunker.boundaries(&data).collect();
+        assert!(bounds.iter().any(|info| info.reason == CutReason::MaxSize));
+        for info in bounds {
+            assert!(info.length <= 4096);
+            assert!(info.reason != CutReason::MaxSize || info.length == 4096);
+        }
+    }
+}
+
+#[cfg(feature = "buzhash")]
+#[test]
+fn buzhash_roll_matches_hash() {
+    let data = generate_data(20_000, 41);
+    let table = generate_seq(3).map(|value| value as u32);
+
+    for window_size in [1, 31, 32, 33, 64, 4095] {
+        let buzhash = BuzHash::new(table, window_size);
+        // value of byte at position pos from the end of window is rotated by pos
+        let direct = |window: &[u8]| {
+            window.iter().rev().enumerate().fold(0u32, |hash, (pos, &cur_val)| {
+                hash ^ table[cur_val as usize].rotate_left(pos as u32)
+            })
+        };
+
+        let mut hash = buzhash.hash(&data[..window_size]);
+        for start in 0..data.len() - window_size {
+            if start % 97 == 0 {
+                assert_eq!(hash, direct(&data[start..start + window_size]), "{window_size} at {start}");
+            }
+            hash = buzhash.roll(hash, data[start], data[start + window_size]);
+        }
+    }
+}
+
+#[cfg(feature = "buzhash")]
+#[test]
+fn buzhash_cuts_at_window_start() {
+    let data = generate_data(300_000, 43);
+    let (min_size, max_size, window_size) = (256, 4096, 64);
+    let table = generate_seq(5).map(|value| value as u32);
+    let chunker = BuzHashConfig::new(1024)
+        .min_size(min_size)
+        .max_size(max_size)
+        .window_size(window_size)
+        .mask_bits(10)
+        .table(table)
+        .seed(0xdead_beef_0123)
+        .build()
+        .unwrap();
+    let buzhash = BuzHash::new(table.map(|value| value ^ 0xbeef_0123), window_size);
+
+    let mut short_chunks = 0;
+    for info in chunker.boundaries(&data) {
+        let offset = info.offset as usize;
+        // window starting at cut must end before the end of data and max_size
+        let limit = (data.len() - offset).min(max_size);
+        for len in min_size..limit.saturating_sub(window_size).min(info.length + 1) {
+            let window = &data[offset + len..offset + len + window_size];
+            let is_zero = buzhash.hash(window) & 1023 == 0;
+            let is_cut = len == info.length && info.reason == CutReason::Hash;
+            assert_eq!(is_zero, is_cut, "at {}", offset + len);
+        }
+        assert!(info.length >= min_size || info.reason == CutReason::Eof);
+        short_chunks += (info.length < min_size + window_size) as usize;
+    }
+    // cut at window start allows chunks shorter than min_size + window_size
+    assert!(short_chunks > 10, "{short_chunks}");
+
+    // borg params give the same chunks as the same explicit config
+    let borg = BuzHashConfig::borg(table, 8, 12, 10, 64).seed(0xdead_beef_0123).build().unwrap();
+    assert_eq!(
+        borg.boundaries(&data).collect::<Vec<_>>(),
+        chunker.boundaries(&data).collect::<Vec<_>>()
     );
-    let mut chunks: Vec<fastcdc::v2020::Chunk> = vec![];
+    assert!(BuzHashConfig::borg(table, 19, 23, 21, 4095).build().is_ok());
+    // borg requires min + window + 1 <= max
+    assert!(BuzHashConfig::borg(table, 10, 12, 11, 3071).build().is_ok());
+    assert!(BuzHashConfig::borg(table, 10, 12, 11, 3072).build().is_err());
+    assert!(BuzHashConfig::borg(table, 10, 12, 33, 64).build().is_err());
+    assert!(is_new_error(BuzHashConfig::new(4096).window_size(usize::MAX).build()));
+}
 
-    for chunk in chunker {
-        chunks.push(chunk);
+#[cfg(feature = "tttd")]
+#[test]
+fn tttd_replaces_max_size_cuts() {
+    let data = generate_data(1_000_000, 17);
+    let rabin = RabinConfig::new(4096).max_size(8192).build().unwrap();
+    let tttd = TttdConfig::new(4096).max_size(8192).build().unwrap();
+    let max_size_cuts = |bounds: Vec<ChunkInfo>| {
+        assert!(bounds.iter().all(|info| info.length <= 8192));
+        bounds.iter().filter(|info| info.reason == CutReason::MaxSize).count()
+    };
+
+    let rabin_cuts = max_size_cuts(rabin.boundaries(&data).collect());
+    let tttd_cuts = max_size_cuts(tttd.boundaries(&data).collect());
+    assert!(tttd_cuts * 2 < rabin_cuts, "{tttd_cuts} of tttd and {rabin_cuts} of rabin");
+}
+
+// nonsensical parameters are reported by ChunkerError::New instead of panic
+#[allow(dead_code)] // only tests of some features use it
+fn is_new_error<T>(result: Result<T, ChunkerError>) -> bool {
+    matches!(result, Err(ChunkerError::New(_)))
+}
+
+#[cfg(feature = "ae")]
+#[test]
+fn ae_config_validation() {
+    assert!(is_new_error(AeConfig::new(0).build()));
+    assert!(is_new_error(AeConfig::new(4096).min_size(4097).build()));
+    assert!(is_new_error(AeConfig::new(4096).max_size(4095).build()));
+    assert!(is_new_error(AeConfig::new(4096).window_size(0).build()));
+    assert!(is_new_error(AeConfig::new(4096).buffer_size(0).build()));
+    assert!(AeConfig::new(4096).min_size(4096).max_size(4096).build().is_ok());
+}
+
+#[cfg(feature = "fastcdc")]
+#[test]
+fn fastcdc_config_validation() {
+    assert!(is_new_error(FastCdcConfig::new(0).build()));
+    assert!(is_new_error(FastCdcConfig::new(4096).min_size(4097).build()));
+    assert!(is_new_error(FastCdcConfig::new(4096).max_size(4095).build()));
+    assert!(is_new_error(FastCdcConfig::new(4096).buffer_size(0).build()));
+    assert!(is_new_error(FastCdcConfig::new(usize::MAX).build()));
+    // normalization level doesn't fit into bits of expected size
+    assert!(is_new_error(FastCdcConfig::new(4).normalization(2).build()));
+
+    // limits of reference FastCDC 2020: min 64..=1 MiB, expected 256..=4 MiB, max 1 KiB..=16 MiB
+    for mode in [FastCdcMode::Compat2020, FastCdcMode::Rolling2020] {
+        let config = |expected_size: usize| FastCdcConfig::new(expected_size).mode(mode);
+        assert!(config(256).min_size(64).max_size(1024).build().is_ok());
+        assert!(config(4 << 20).min_size(1 << 20).max_size(16 << 20).build().is_ok());
+        assert!(is_new_error(config(4096).min_size(63).build()));
+        assert!(is_new_error(config(2 << 20).min_size((1 << 20) + 1).build()));
+        assert!(is_new_error(config(255).min_size(64).max_size(1024).build()));
+        assert!(is_new_error(config((4 << 20) + 1).min_size(1 << 20).max_size(16 << 20).build()));
+        assert!(is_new_error(config(256).min_size(64).max_size(1023).build()));
+        assert!(is_new_error(config(4 << 20).min_size(1 << 20).max_size((16 << 20) + 1).build()));
+        // reference gear table can't be replaced
+        assert!(is_new_error(config(4096).gear([1; 256]).build()));
     }
-    chunks
 }
 
-fn fcdc_test(path: &str, n: usize) -> Vec<Vec<u8>> {
-    let f = File::open(path).unwrap();
-    let mut reader = BufReader::new(&f);
-    let mut chunker = FastCdcChunker::new(1024 * n, 1);
+#[cfg(feature = "rabin")]
+#[test]
+fn rabin_config_validation() {
+    assert!(is_new_error(RabinConfig::new(0).build()));
+    assert!(is_new_error(RabinConfig::new(4096).min_size(4097).build()));
+    assert!(is_new_error(RabinConfig::new(4096).max_size(4095).build()));
+    assert!(is_new_error(RabinConfig::new(4096).window_size(0).build()));
+    assert!(is_new_error(RabinConfig::new(4096).buffer_size(0).build()));
+    assert!(is_new_error(RabinConfig::new((1 << 31) + 1).build()));
+    // default window expected / 4 - 1 is empty for tiny expected sizes
+    assert!(is_new_error(RabinConfig::new(4).build()));
+    assert!(is_new_error(RabinConfig::new(4096).polynomial(0xff).build()));
+}
 
-    let result = test_chunking(&mut chunker, &mut reader);
-    result
+#[cfg(feature = "fastcdc")]
+#[test]
+fn fastcdc_normalization_levels() {
+    for bits in 8..=22 {
+        for level in 0..=3 {
+            assert!(FastCdcConfig::new(1 << bits).normalization(level).build().is_ok());
+        }
+    }
+    assert!(FastCdcConfig::new(8192).normalization(4).build().is_err());
+
+    // higher level gives chunk sizes closer to expected one
+    let data = generate_data(4_000_000, 29);
+    let deviations: Vec<f64> = (0..=3)
+        .map(|level| {
+            let chunker = FastCdcConfig::new(8192).normalization(level).build().unwrap();
+            size_stats(&chunker.boundaries(&data).collect::<Vec<_>>()).1
+        })
+        .collect();
+    assert!(deviations.windows(2).all(|pair| pair[0] > pair[1]), "{deviations:?}");
 }
 
-fn ae_test(path: &str, n: usize) -> Vec<Vec<u8>> {
-    let f = File::open(path).unwrap();
-    let mut reader = BufReader::new(&f);
-    let mut chunker = AeChunker::new(1024 * n);
+#[cfg(feature = "fastcdc")]
+#[test]
+fn fastcdc_mean_tracks_expected_size() {
+    let data = random_data(8_000_000, 5);
 
-    let result = test_chunking(&mut chunker, &mut reader);
-    result
+    for expected_size in [3000, 8192, 12 << 10, 20_000] {
+        for level in 0..=3 {
+            let chunker = FastCdcConfig::new(expected_size).normalization(level).build().unwrap();
+            let (mean, _) = size_stats(&chunker.boundaries(&data).collect::<Vec<_>>());
+            let ratio = mean / expected_size as f64;
+            assert!((1.0..1.3).contains(&ratio), "{expected_size} level {level}: {ratio}");
+        }
+    }
 }
 
-fn rabin_test(path: &str, n: usize) -> Vec<Vec<u8>> {
-    let f = File::open(path).unwrap();
-    let mut reader = BufReader::new(&f);
-    let mut chunker = RabinChunker::new(1024 * n, 0);
+// thresholds of judgment-based chunkers are computed from expected size
+#[cfg(all(feature = "leap", feature = "pci"))]
+#[test]
+fn leap_and_pci_mean_tracks_expected_size() {
+    let data = random_data(8_000_000, 7);
+
+    for expected_size in [1024, 4096, 12 << 10, 65536] {
+        let chunkers: [(&str, Box<dyn Chunker>); 2] = [
+            ("leap", Box::new(LeapCdcChunker::new(expected_size, 0).unwrap())),
+            ("pci", Box::new(PciChunker::new(expected_size).unwrap())),
+        ];
+        for (name, chunker) in chunkers {
+            let (mean, _) = size_stats(&chunker.boundaries(&data).collect::<Vec<_>>());
+            let ratio = mean / expected_size as f64;
+            // popcount threshold is integer, its neighbour values change rate of cuts by 20-40%
+            assert!((0.75..1.25).contains(&ratio), "{name} {expected_size}: {ratio}");
+        }
+    }
+
+    assert!(is_new_error(LeapCdcConfig::new(4096).window_size(usize::MAX).build()));
+    assert!(is_new_error(
+        LeapCdcConfig::new(4096)
+            .window_size(usize::MAX - 16_384)
+            .windows_count(24)
+            .build()
+    ));
+}
 
-    let result = test_chunking(&mut chunker, &mut reader);
-    result
-}
\ No newline at end of file
+#[cfg(feature = "fastcdc")]
+fn fastcdc_2020(
+    mode: FastCdcMode,
+    min_size: usize,
+    avg_size: usize,
+    max_size: usize,
+    level: usize,
+    seed: u64,
+) -> FastCdcChunker {
+    FastCdcConfig::new(avg_size)
+        .min_size(min_size)
+        .max_size(max_size)
+        .normalization(level)
+        .seed(seed)
+        .mode(mode)
+        .build()
+        .unwrap()
+}
+
+#[cfg(feature = "fastcdc")]
+#[test]
+fn fastcdc_2020_matches_reference() {
+    use fastcdc::v2020::{FastCDC, Normalization};
+
+    let levels = [
+        Normalization::Level0,
+        Normalization::Level1,
+        Normalization::Level2,
+        Normalization::Level3,
+    ];
+    for (data_seed, len) in [(1, 1_000_000), (2, 777_777), (3, 100)] {
+        let data = generate_data(len, data_seed);
+        for (min_size, avg_size, max_size) in [
+            (64, 256, 1024),
+            (2048, 8192, 65536),
+            (4096, 16384, 65535),
+            (8191, 32768, 131072),
+            (65536, 262144, 1 << 20),
+            // masks of non-power-of-two sizes are rounded as in reference
+            (2048, 6000, 65536),
+            (2048, 11_000, 65536),
+        ] {
+            for (level, normalization) in levels.iter().enumerate() {
+                for seed in [0, 666] {
+                    let expected: Vec<(usize, usize)> = FastCDC::with_level_and_seed(
+                        &data,
+                        min_size as u32,
+                        avg_size as u32,
+                        max_size as u32,
+                        *normalization,
+                        seed,
+                    )
+                    .map(|chunk| (chunk.offset, chunk.length))
+                    .collect();
+
+                    for mode in [FastCdcMode::Compat2020, FastCdcMode::Rolling2020] {
+                        let chunker = fastcdc_2020(mode, min_size, avg_size, max_size, level, seed);
+                        let bounds: Vec<ChunkInfo> = chunker.boundaries(&data).collect();
+                        let ranges: Vec<(usize, usize)> = bounds
+                            .iter()
+                            .map(|info| (info.offset as usize, info.length))
+                            .collect();
+                        let params = format!("{min_size}/{avg_size}/{max_size} level {level} seed {seed} {mode:?}");
+                        assert_eq!(ranges, expected, "{params}");
+
+                        let stream = stream_chunks(Box::new(chunker), &mut split_reader("random", &data));
+                        assert_eq!(stream, bounds, "{params}");
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "fastcdc")]
+#[test]
+fn fastcdc_2020_golden_vectors() {
+    let data = generate_data(1_000_000, 42);
+    let lengths = |chunker: FastCdcChunker| -> Vec<usize> {
+        chunker.boundaries(&data).take(12).map(|info| info.length).collect()
+    };
+
+    for mode in [FastCdcMode::Compat2020, FastCdcMode::Rolling2020] {
+        assert_eq!(lengths(fastcdc_2020(mode, 2048, 8192, 65536, 1, 0)), GOLDEN_8K, "{mode:?}");
+        assert_eq!(lengths(fastcdc_2020(mode, 4096, 16384, 65536, 2, 666)), GOLDEN_16K, "{mode:?}");
+    }
+}
+
+#[cfg(feature = "fastcdc")]
+const GOLDEN_8K: [usize; 12] = [
+    21442, 4335, 5411, 3310, 16965, 5493, 4146, 9430, 9409, 12503, 23888, 9901,
+];
+#[cfg(feature = "fastcdc")]
+const GOLDEN_16K: [usize; 12] = [
+    27558, 12629, 21054, 18913, 19230, 27625, 23225, 19082, 16809, 30257, 18909, 16723,
+];
+
+// fingerprint of window computed directly as polynomial over GF(2) modulo pol
+#[cfg(feature = "rabin")]
+fn gf2_fingerprint(window: &[u8], pol: u64) -> u64 {
+    let pol_degree = 63 - pol.leading_zeros();
+    window.iter().fold(0u64, |hash, &cur_val| {
+        let mut value = ((hash as u128) << 8) | cur_val as u128;
+        for bit in (pol_degree..72).rev() {
+            if (value >> bit) & 1 == 1 {
+                value ^= (pol as u128) << (bit - pol_degree);
+            }
+        }
+        value as u64
+    })
+}
+
+#[cfg(feature = "rabin")]
+#[test]
+fn rabin_fingerprint_matches_direct_computation() {
+    let data = generate_data(200_000, 13);
+    // LBFS polynomial of degree 63 and restic test polynomial of degree 53
+    for pol in [DEFAULT_POLYNOMIAL, 0x3DA3358B4DC173] {
+        let chunker = RabinConfig::new(1024)
+            .min_size(256)
+            .max_size(4096)
+            .window_size(64)
+            .polynomial(pol)
+            .mode(RabinMode::Fingerprint)
+            .build()
+            .unwrap();
+
+        let mut hash_cuts = 0;
+        for info in chunker.boundaries(&data) {
+            let chunk = &data[info.range()];
+            // only the last position of chunk may have zero fingerprint under mask
+            for len in 256..=chunk.len() {
+                let is_zero = gf2_fingerprint(&chunk[len - 64..len], pol) & 1023 == 0;
+                let is_cut = len == chunk.len() && info.reason == CutReason::Hash;
+                assert_eq!(is_zero, is_cut, "{pol:x} at {}", info.offset as usize + len);
+            }
+            hash_cuts += (info.reason == CutReason::Hash) as usize;
+        }
+        assert!(hash_cuts > 100);
+    }
+
+    // x^16 + 1 = (x + 1)^16
+    assert!(RabinConfig::new(1024).polynomial(0x10001).build().is_err());
+}
+
+#[cfg(feature = "rsync")]
+#[test]
+fn rsync_checksum_matches_direct_computation() {
+    let data = generate_data(200_000, 19);
+    // s1 = sum of bytes, s2 = sum of bytes weighted by distance to window end, both modulo 2^16
+    let direct = |window: &[u8]| {
+        let s1: u32 = window.iter().map(|&b| b as u32).sum();
+        let s2: u32 = window.iter().rev().enumerate().map(|(i, &b)| (i as u32 + 1) * b as u32).sum();
+        (s1 & 0xffff) | (s2 << 16)
+    };
+
+    let mut checksum = RollingChecksum::new(&data[..64]);
+    for end in 64..data.len() {
+        assert_eq!(checksum.digest(), direct(&data[end - 64..end]), "at {end}");
+        checksum.roll(data[end - 64], data[end]);
+    }
+
+    let chunker = RsyncConfig::new(1024).min_size(256).max_size(4096).window_size(64).build().unwrap();
+    let mut hash_cuts = 0;
+    for info in chunker.boundaries(&data) {
+        let chunk = &data[info.range()];
+        // only the last position of chunk may have checksum matching mask
+        for len in 256..=chunk.len() {
+            let is_zero = direct(&chunk[len - 64..len]).rotate_left(16) & 1023 == 0;
+            let is_cut = len == chunk.len() && info.reason == CutReason::Hash;
+            assert_eq!(is_zero, is_cut, "at {}", info.offset as usize + len);
+        }
+        hash_cuts += (info.reason == CutReason::Hash) as usize;
+    }
+    assert!(hash_cuts > 100);
+}
+
+// ustar header block with given name, size field and type
+#[cfg(all(feature = "tar", feature = "gear"))]
+fn tar_header(name: &str, size: usize, typeflag: u8) -> Vec<u8> {
+    let mut header = vec![0u8; 512];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    header[124..136].copy_from_slice(format!("{size:011o}\0").as_bytes());
+    header[156] = typeflag;
+    header[257..265].copy_from_slice(b"ustar\x0000");
+    header[148..156].copy_from_slice(b"        ");
+    let sum: u32 = header.iter().map(|&b| b as u32).sum();
+    header[148..156].copy_from_slice(format!("{sum:06o}\0 ").as_bytes());
+    header
+}
+
+#[cfg(all(feature = "tar", feature = "gear"))]
+#[test]
+fn tar_cuts_at_member_boundaries() {
+    let pad = |mut data: Vec<u8>| {
+        data.resize(data.len().div_ceil(512) * 512, 0);
+        data
+    };
+    let pax_records = b"14 size=30000\n".to_vec();
+    // headers of members and their data, pax size record overrides size field of the next header
+    let members = |first: Vec<u8>| {
+        vec![
+            (tar_header("a.bin", first.len(), b'0'), pad(first)),
+            (tar_header("dir", 0, b'5'), vec![]),
+            (tar_header("empty", 0, b'0'), vec![]),
+            (tar_header("b.bin", 513, b'0'), pad(generate_data(513, 2))),
+            (
+                [
+                    tar_header("pax", pax_records.len(), b'x'),
+                    pad(pax_records.clone()),
+                    tar_header("c.bin", 7, b'0'),
+                ]
+                .concat(),
+                pad(generate_data(30_000, 3)),
+            ),
+        ]
+    };
+    let archive = |members: &[(Vec<u8>, Vec<u8>)]| {
+        let mut data: Vec<u8> = members.iter().flat_map(|(h, d)| [&h[..], &d[..]].concat()).collect();
+        data.extend_from_slice(&[0u8; 1024]);
+        data
+    };
+
+    let inner = GearChunker::new(4096, 0).unwrap();
+    let chunker = TarChunker::new(GearChunker::new(4096, 0).unwrap());
+    let first = generate_data(70_000, 1);
+    let data = archive(&members(first.clone()));
+    let chunks: Vec<ChunkInfo> = chunker.boundaries(&data).collect();
+
+    // headers are one chunk, data (and end of archive) is chunked by inner chunker as the whole input
+    let mut expected = Vec::new();
+    for (headers, member_data) in members(first.clone()) {
+        expected.push(headers.len());
+        expected.extend(inner.boundaries(&member_data).map(|info| info.length));
+    }
+    expected.extend(inner.boundaries(&[0u8; 1024]).map(|info| info.length));
+    assert_eq!(chunks.iter().map(|info| info.length).collect::<Vec<_>>(), expected);
+    assert_eq!(chunks.last().unwrap().reason, CutReason::Eof);
+    assert!(chunks[..chunks.len() - 1].iter().all(|info| info.reason != CutReason::Eof));
+
+    for threads_cnt in 1..=8 {
+        assert_eq!(chunker.parallel_boundaries(&data, threads_cnt), chunks);
+    }
+
+    // position in archive is state of drivers, not of chunker: cuts don't depend on earlier ones
+    let partial: Vec<ChunkInfo> = Chunker::boundaries(&chunker, &data).take(2).collect();
+    assert_eq!(partial, chunks[..2]);
+    assert_eq!(Chunker::boundaries(&chunker, &data).collect::<Vec<_>>(), chunks);
+    assert_eq!(chunker.find_cut(&data), (512, CutReason::Member));
+    assert_eq!(chunker.find_cut(&data), (512, CutReason::Member));
+    assert_eq!(parallel_boundaries(&chunker, &data, 4), chunks);
+    let boxed: Box<dyn Chunker> = Box::new(TarChunker::new(GearChunker::new(4096, 0).unwrap()));
+    assert_eq!(stream_chunks(boxed, &mut &data[..]), chunks);
+
+    // position in archive is tracked between chunks of stream, also for truncated archives
+    let hashed: Vec<ChunkInfo> = chunker.boundaries(&data).with_strong_hash().collect();
+    assert!(hashed.iter().all(|info| info.hash == Some(strong_hash(&data[info.range()]))));
+    for len in [data.len(), 300, 512 + 70_000, 512 * 140 + 700, 512 * 140 + 1700, data.len() - 1000] {
+        let data = &data[..len];
+        let expected: Vec<ChunkInfo> = chunker.boundaries(data).collect();
+        assert_eq!(chunker.parallel_boundaries(data, 4), expected, "{len} bytes");
+        for buffer_size in [1, 1000, DEFAULT_BUFFER_SIZE] {
+            for reader_name in ["1-byte", "odd", "random"] {
+                let mut streaming =
+                    TarChunker::new(GearChunker::new(4096, 0).unwrap()).buffer_size(buffer_size);
+                streaming.set_strong_hash(true);
+                let streamed: Vec<ChunkInfo> =
+                    ChunkIter::new(&mut streaming, split_reader(reader_name, data))
+                        .map(|chunk| chunk.unwrap().info)
+                        .collect();
+                let hashed: Vec<ChunkInfo> = chunker.boundaries(data).with_strong_hash().collect();
+                assert_eq!(
+                    streamed, hashed,
+                    "{len} bytes with {reader_name} reads and buffer size {buffer_size}"
+                );
+            }
+        }
+    }
+
+    // change of one member keeps chunks of others
+    let mut changed = first.clone();
+    changed[100] ^= 1;
+    let changed_data = archive(&members(changed));
+    let member_end = 512 + pad(first).len() as u64;
+    let after = |chunks: Vec<ChunkInfo>| -> Vec<ChunkInfo> {
+        chunks.into_iter().filter(|info| info.offset >= member_end).collect()
+    };
+    assert_eq!(after(chunker.boundaries(&changed_data).collect()), after(chunks));
+}
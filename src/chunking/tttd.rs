@@ -0,0 +1,155 @@
+use super::chunker::*;
+use super::rabin::HashRabin;
+
+// "A Framework for Analyzing and Improving Content-Based Chunking Algorithms" (Two Thresholds Two Divisors)
+// Rabin hash of RabinChunker with main divisor and smaller backup one,
+// when max size is reached chunk is cut at the last backup match instead of max size
+
+// parameters of TttdChunker
+#[derive(Clone, Debug)]
+pub struct TttdConfig {
+    min_size: usize,
+    expected_size: usize, // main divisor
+    backup_size: usize,   // backup divisor
+    max_size: usize,
+    window_size: usize, // rabin hash window size
+    alpha: u32,         // base of polynomial hash
+    seed: u32,          // value hash is compared with
+    buffer_size: usize, // initial size of buffer for reading from input
+}
+
+impl TttdConfig {
+    pub fn new(expected_size: usize) -> TttdConfig {
+        TttdConfig {
+            min_size: expected_size / 4,
+            expected_size,
+            // backup divisor is half of main one in the paper
+            backup_size: (expected_size / 2).max(1),
+            max_size: expected_size.saturating_mul(4),
+            window_size: (expected_size / 4).saturating_sub(1),
+            alpha: 1_664_525,
+            seed: 0,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+        }
+    }
+
+    pub fn min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    pub fn backup_size(mut self, backup_size: usize) -> Self {
+        self.backup_size = backup_size;
+        self
+    }
+
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    pub fn window_size(mut self, window_size: usize) -> Self {
+        self.window_size = window_size;
+        self
+    }
+
+    pub fn alpha(mut self, alpha: u32) -> Self {
+        self.alpha = alpha;
+        self
+    }
+
+    pub fn seed(mut self, seed: u32) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    pub fn build(self) -> Result<TttdChunker, ChunkerError> {
+        if self.expected_size == 0 {
+            return Err(ChunkerError::New("expected size must be positive"));
+        }
+        if self.expected_size > 1 << 31 {
+            return Err(ChunkerError::New("expected size is too big"));
+        }
+        if self.min_size > self.expected_size || self.expected_size > self.max_size {
+            return Err(ChunkerError::New(
+                "sizes must satisfy min <= expected <= max",
+            ));
+        }
+        if self.backup_size == 0 || self.backup_size > self.expected_size {
+            return Err(ChunkerError::New(
+                "backup size must satisfy 0 < backup <= expected",
+            ));
+        }
+        if self.window_size == 0 {
+            return Err(ChunkerError::New("window size must be positive"));
+        }
+        if self.buffer_size == 0 {
+            return Err(ChunkerError::New("buffer size must be positive"));
+        }
+
+        Ok(TttdChunker {
+            stream: ChunkStream::new(self.buffer_size),
+            window_size: self.window_size,
+            min_size: self.min_size,
+            max_size: self.max_size,
+            hash: HashRabin::new(self.expected_size, self.window_size, self.alpha, self.seed),
+            backup_mask: (self.backup_size as u32).next_power_of_two() - 1,
+            config: self,
+        })
+    }
+}
+
+pub struct TttdChunker {
+    stream: ChunkStream, // for buffered reading from input
+    window_size: usize,  // rabin hash window size
+    min_size: usize,     // min size of chunk
+    max_size: usize,     // max size of chunk
+    hash: HashRabin,     // rabin hash with main mask
+    backup_mask: u32,    // mask of backup divisor
+    config: TttdConfig,  // parameters chunker was built with
+}
+
+impl TttdChunker {
+    pub fn new(expected_size: usize, seed: u32) -> Result<TttdChunker, ChunkerError> {
+        TttdConfig::new(expected_size).seed(seed).build()
+    }
+
+    pub fn config(&self) -> &TttdConfig {
+        &self.config
+    }
+}
+
+impl Chunker for TttdChunker {
+    fn find_cut(&self, data: &[u8]) -> (usize, CutReason) {
+        let mut hash = 0u32;
+        let mut backup = None;
+        for i in 0..data.len().min(self.max_size) {
+            hash = self.hash.roll(hash, data, i, self.window_size);
+            if i >= self.window_size && i >= self.min_size {
+                if self.hash.matches(hash, self.hash.mask) {
+                    return (i + 1, CutReason::Hash);
+                }
+                if self.hash.matches(hash, self.backup_mask) {
+                    backup = Some(i + 1);
+                }
+            }
+        }
+
+        if data.len() < self.max_size {
+            return (data.len(), CutReason::Eof);
+        }
+        match backup {
+            Some(cut) => (cut, CutReason::Hash),
+            None => (self.max_size, CutReason::MaxSize),
+        }
+    }
+
+    fn stream(&mut self) -> &mut ChunkStream {
+        &mut self.stream
+    }
+}
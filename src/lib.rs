@@ -0,0 +1,41 @@
+//! Implementations of the main CDC (content-defined chunking) algorithms.
+//!
+//! Every algorithm is behind a cargo feature of the same name (all enabled by default)
+//! and implements [`Chunker`], which gives both streaming (`next_chunk`, [`ChunkIter`])
+//! and zero-copy (`find_cut`, [`Boundaries`]) chunking.
+
+pub mod chunking;
+
+pub use chunking::chunker::{
+    strong_hash, Boundaries, ChunkInfo, ChunkStream, Chunker, ChunkerError, ChunkerStatus,
+    CutReason,
+};
+pub use chunking::iter::{Chunk, ChunkIter};
+pub use chunking::parallel::{dedup_stats, parallel_boundaries, parallel_chunking, size_stats};
+
+#[cfg(feature = "ae")]
+pub use chunking::ae::{AeChunker, AeConfig, AeExtremum, AeValue};
+#[cfg(feature = "buzhash")]
+pub use chunking::buzhash::{BuzHashChunker, BuzHashConfig};
+#[cfg(feature = "fastcdc")]
+pub use chunking::fastcdc::{FastCdcChunker, FastCdcConfig, FastCdcMode};
+#[cfg(feature = "fixed")]
+pub use chunking::fixed::FixedChunker;
+#[cfg(feature = "gear")]
+pub use chunking::gear::{GearChunker, GearConfig};
+#[cfg(feature = "leap")]
+pub use chunking::leap::{LeapCdcChunker, LeapCdcConfig};
+#[cfg(feature = "maxp")]
+pub use chunking::maxp::{MaxpChunker, MaxpConfig};
+#[cfg(feature = "pci")]
+pub use chunking::pci::{PciChunker, PciConfig};
+#[cfg(feature = "rabin")]
+pub use chunking::rabin::{RabinChunker, RabinConfig, RabinMode, DEFAULT_POLYNOMIAL};
+#[cfg(feature = "ram")]
+pub use chunking::ram::{RamChunker, RamConfig};
+#[cfg(feature = "rsync")]
+pub use chunking::rsync::{RollingChecksum, RsyncChunker, RsyncConfig};
+#[cfg(feature = "tar")]
+pub use chunking::tar::TarChunker;
+#[cfg(feature = "tttd")]
+pub use chunking::tttd::{TttdChunker, TttdConfig};